@@ -19,6 +19,413 @@
     let reconnectTimer = null;
     let reconnectAttempts = 0;
     let isReconnecting = false;
+    let rebuildPending = false;
+    let webSocketOpened = false;
+    // Sequence number of the last reload seen, so a reconnect can catch up on missed ones
+    let lastSequence = null;
+    // Whether this page has reached the server yet, reconnects compare the server's boot id
+    let hasConnected = false;
+    // Globs set through Vigil.subscribe(), re-sent on every reconnect, and the socket to send them on
+    let subscriptionPatterns = null;
+    let currentSocket = null;
+    
+    // Failed WebSocket attempts before switching to Server-Sent Events (substituted by the server)
+    const sseFallbackRetries = parseInt('__VIGIL_SSE_FALLBACK_RETRIES__', 10) || 3;
+    
+    // Reverse proxy settings (substituted by the server): the path prefix the app is served under
+    // and an explicit socket URL, empty to connect to this page's host
+    const unsubstituted = (value) => value.startsWith('__VIGIL_') ? '' : value;
+    const routePrefix = unsubstituted('__VIGIL_ROUTE_PREFIX__');
+    const publicSocketUrl = unsubstituted('__VIGIL_PUBLIC_WS_URL__');
+    const socketUrl = publicSocketUrl || `${window.location.protocol === 'https:' ? 'wss' : 'ws'}://${window.location.host}${routePrefix}/ws/dev/reload`;
+    
+    // Message protocol requested from the server, older servers ignore it and send plain strings
+    const PROTOCOL_VERSION = 1;
+    const WEBSOCKET_PROTOCOL = 'vigil-v1';
+    
+    // Set by the page this script was injected into when the server requires a dev_token
+    const devToken = (document.currentScript && document.currentScript.dataset.vigilToken) || '';
+    const tokenParam = devToken ? `&token=${encodeURIComponent(devToken)}` : '';
+    
+    // Close codes the server sends on purpose: a protocol mismatch won't fix itself, an internal
+    // error or a full server is retried from a longer backoff instead of right away
+    const CLOSE_PROTOCOL_ERROR = 1002;
+    const SLOW_RETRY_CLOSE_CODES = [1011, 1013];
+    
+    // Show a status overlay in the corner of the page, replacing any previous text
+    function showStatusOverlay(text) {
+        let overlay = document.getElementById('vigil-status-overlay');
+        if (!overlay) {
+            overlay = document.createElement('div');
+            overlay.id = 'vigil-status-overlay';
+            overlay.style.cssText = 'position:fixed;bottom:16px;right:16px;z-index:2147483647;' +
+                'padding:10px 16px;border-radius:6px;background:#8c16a1;color:#fff;' +
+                'font:14px/1.4 sans-serif;box-shadow:0 2px 8px rgba(0,0,0,0.3);';
+            document.body.appendChild(overlay);
+        }
+        overlay.textContent = text;
+    }
+    
+    function hideStatusOverlay() {
+        document.getElementById('vigil-status-overlay')?.remove();
+    }
+    
+    // Show an overlay while the server is rebuilding
+    function showRebuildOverlay(filePath) {
+        showStatusOverlay(`Rebuilding… (${filePath})`);
+    }
+    
+    // Changed paths arrive as a JSON array, so they may contain colons or other separators.
+    // Plain payloads (e.g. a manual `reload:manual`) are treated as a single entry.
+    function parseFileList(payload) {
+        if (payload.startsWith('[')) {
+            try {
+                return JSON.parse(payload);
+            } catch (e) {
+                console.warn('[Vigil] Could not parse batched file list:', payload);
+            }
+        }
+        return [payload];
+    }
+    
+    // Swap a changed stylesheet in place, returns false if no matching <link> was found
+    function swapStylesheet(filePath, publicUrl) {
+        // Compiled stylesheets keep the source name, so match on the name without extension,
+        // unless a <link> loads exactly the changed file
+        const changedName = filePath.split('/').pop().replace(/\.(s?css|sass|less)$/i, '');
+        const links = Array.from(document.querySelectorAll('link[rel=stylesheet]'));
+        const exact = links.filter((link) => new URL(link.href, window.location.href).pathname === publicUrl);
+        let swapped = false;
+        
+        (exact.length > 0 ? exact : links).forEach((link) => {
+            const url = new URL(link.href, window.location.href);
+            const linkName = url.pathname.split('/').pop().replace(/(\.min)?\.css$/, '');
+            if (exact.length > 0 || linkName === changedName) {
+                url.searchParams.set('vigil', Date.now());
+                link.href = url.toString();
+                swapped = true;
+            }
+        });
+        
+        return swapped;
+    }
+    
+    // Refresh images and icons referencing a changed asset, returns false if nothing references it
+    function refreshAsset(filePath, publicUrl) {
+        const changedName = filePath.split('/').pop();
+        let refreshed = false;
+        
+        document.querySelectorAll('img[src], link[href]:not([rel=stylesheet])').forEach((element) => {
+            const attribute = element.tagName === 'IMG' ? 'src' : 'href';
+            const url = new URL(element.getAttribute(attribute), window.location.href);
+            if (publicUrl ? url.pathname === publicUrl : url.pathname.split('/').pop() === changedName) {
+                url.searchParams.set('vigil', Date.now());
+                element.setAttribute(attribute, url.toString());
+                refreshed = true;
+            }
+        });
+        
+        return refreshed;
+    }
+    
+    // Swap changed stylesheets in place, reloading once if any stylesheet can't be swapped
+    function applyStylesheetChanges(filePaths, state, publicUrls) {
+        const missing = filePaths.filter((filePath) => !swapStylesheet(filePath, publicUrls[filePath]));
+        if (missing.length === 0) {
+            console.log(`[Vigil] Stylesheet changed: ${filePaths.join(', ')}, swapped in place`);
+            state.ignoreTimestampChange = true;
+        } else {
+            console.log(`[Vigil] Stylesheet changed: ${missing.join(', ')}, no matching <link> found, reloading...`);
+            window.location.reload();
+        }
+    }
+    
+    // Refresh the elements using changed assets, reloading once if any asset isn't referenced
+    function applyAssetChanges(filePaths, state, publicUrls) {
+        const missing = filePaths.filter((assetPath) => !refreshAsset(assetPath, publicUrls[assetPath]));
+        if (missing.length === 0) {
+            console.log(`[Vigil] Asset changed: ${filePaths.join(', ')}, refreshed in place`);
+            state.ignoreTimestampChange = true;
+        } else {
+            console.log(`[Vigil] Asset changed: ${missing.join(', ')}, no matching element found, reloading...`);
+            window.location.reload();
+        }
+    }
+    
+    // Values typed into form fields, keyed by id or name, so a re-rendered page can get them back
+    function collectFormState() {
+        const values = new Map();
+        document.querySelectorAll('input, textarea, select').forEach((field) => {
+            const key = field.id || field.name;
+            if (!key || field.type === 'password' || field.type === 'file') {
+                return;
+            }
+            values.set(key, field.type === 'checkbox' || field.type === 'radio' ? field.checked : field.value);
+        });
+        return values;
+    }
+    
+    function restoreFormState(values) {
+        document.querySelectorAll('input, textarea, select').forEach((field) => {
+            const key = field.id || field.name;
+            if (!values.has(key) || field.type === 'radio') {
+                return;
+            }
+            if (field.type === 'checkbox') {
+                field.checked = values.get(key);
+            } else {
+                field.value = values.get(key);
+            }
+        });
+    }
+    
+    // A template changed, render the page again and swap in its body instead of reloading. Scripts in
+    // the new body don't run, pages that build their content in JavaScript should keep partial_reload off.
+    function applyPartialReload(filePaths, state) {
+        fetch(window.location.href, { credentials: 'same-origin' })
+            .then((response) => {
+                if (!response.ok) {
+                    throw new Error(`HTTP ${response.status}`);
+                }
+                return response.text();
+            })
+            .then((html) => {
+                const page = new DOMParser().parseFromString(html, 'text/html');
+                const formState = collectFormState();
+                const scroll = [window.scrollX, window.scrollY];
+                
+                document.title = page.title;
+                document.body.innerHTML = page.body.innerHTML;
+                restoreFormState(formState);
+                window.scrollTo(scroll[0], scroll[1]);
+                
+                console.log(`[Vigil] Template changed: ${filePaths.join(', ')}, re-rendered in place`);
+                state.ignoreTimestampChange = true;
+            })
+            .catch((error) => {
+                console.log(`[Vigil] Template changed: ${filePaths.join(', ')}, could not re-render (${error.message}), reloading...`);
+                window.location.reload();
+            });
+    }
+    
+    // Rust source changed, wait for the server to restart before reloading
+    function startRebuild(filePath) {
+        console.log(`[Vigil] Rust source changed: ${filePath}, waiting for rebuild...`);
+        rebuildPending = true;
+        showRebuildOverlay(filePath);
+    }
+    
+    // Handle a JSON change message: { action, events: [{ path, url, kind, category, timestamp, previous_path }] }
+    // with paths relative to the project root and a URL for files under public/
+    function handleChangeEvents(payload, state) {
+        const events = payload.events || [];
+        const filePaths = events.map((event) => event.path);
+        const publicUrls = Object.fromEntries(events.filter((event) => event.url).map((event) => [event.path, event.url]));
+        
+        switch (payload.action) {
+            case 'css-swap':
+                applyStylesheetChanges(filePaths, state, publicUrls);
+                break;
+            case 'asset':
+                applyAssetChanges(filePaths, state, publicUrls);
+                break;
+            case 'partial':
+                applyPartialReload(filePaths, state);
+                break;
+            case 'rebuild':
+                startRebuild((events.find((event) => event.category === 'Rust source') || events[0] || {}).path);
+                break;
+            default: {
+                // A git pull or full rebuild sends dozens of files at once, keep them out of the way
+                const count = payload.count || events.length;
+                if (count > 1) {
+                    console.groupCollapsed(`[Vigil] ${count} files changed`);
+                }
+                events.forEach((event) => {
+                    const previous = event.previous_path ? `${event.previous_path} -> ` : '';
+                    console.log(`[Vigil] ${event.category} ${event.kind}: ${previous}${event.path}`);
+                });
+                if (count > 1) {
+                    console.groupEnd();
+                }
+                // The id matches the server's log lines for this connection
+                console.log(state.connectionId ? `[Vigil] Reloading... [id=${state.connectionId}]` : '[Vigil] Reloading...');
+                window.location.reload();
+            }
+        }
+    }
+    
+    // Heartbeat with the server's latest modification time, reloads if it moved without a change message
+    function handleTimestamp(serverTimestamp, state) {
+        if (serverTimestamp > state.lastChangeTimestamp) {
+            if (state.ignoreTimestampChange) {
+                // Change was already handled without a reload
+                state.ignoreTimestampChange = false;
+            } else if (state.lastChangeTimestamp > 0 && !rebuildPending && !subscriptionPatterns) {
+                console.log(`[Vigil] File changes detected, reloading...`);
+                window.location.reload();
+            } else {
+                console.debug(`[Vigil] Initial timestamp: ${serverTimestamp}`);
+            }
+            state.lastChangeTimestamp = serverTimestamp;
+        }
+    }
+    
+    // The server is shutting down. Reconnects wait for it like after a rebuild, then reload once since
+    // templates or code may have changed in between.
+    function waitForRestart() {
+        console.log('[Vigil] Server stopped, reloading once it is back...');
+        if (!rebuildPending) {
+            rebuildPending = true;
+            showStatusOverlay('Server stopped, waiting for restart…');
+        }
+    }
+    
+    // The server refused the connection because too many pages are connected, it's retried with backoff
+    function showRateLimited(limit) {
+        const limitText = limit ? ` (at most ${limit})` : '';
+        console.warn(`[Vigil] Too many open hot reload connections${limitText}, close some tabs or raise max_ws_connections`);
+        showStatusOverlay('Too many Vigil tabs open…');
+    }
+    
+    // Remember the server's boot id for this tab, returns true and reloads if the server restarted
+    // since this page last reached it
+    function checkBootId(bootId) {
+        if (!bootId) {
+            return false;
+        }
+        let previousBootId = null;
+        try {
+            previousBootId = sessionStorage.getItem('vigil-boot-id');
+            sessionStorage.setItem('vigil-boot-id', bootId);
+        } catch (e) {
+            // Storage can be disabled, restarts then only show up through change messages
+        }
+        
+        const restarted = hasConnected && previousBootId !== null && previousBootId !== bootId;
+        hasConnected = true;
+        if (restarted) {
+            console.log('[Vigil] Server restarted, reloading...');
+            window.location.reload();
+        }
+        return restarted;
+    }
+    
+    // Handle a protocol 1 message, a JSON object tagged with `type` (see /vigil/protocol.json)
+    function handleProtocolMessage(payload, state) {
+        switch (payload.type) {
+            case 'connected':
+                if (checkBootId(payload.boot)) {
+                    break;
+                }
+                state.connectionId = String(payload.id);
+                if (lastSequence === null) {
+                    lastSequence = payload.seq;
+                }
+                console.log(`[Vigil] Connected [id=${state.connectionId}]`);
+                break;
+            case 'ping':
+                handleTimestamp(payload.timestamp, state);
+                break;
+            case 'reload':
+                // A reload sent while the replay was being prepared can arrive twice
+                if (lastSequence !== null && payload.seq <= lastSequence) {
+                    break;
+                }
+                lastSequence = payload.seq;
+                if (payload.delay > 0) {
+                    // Give build steps such as a SASS compile time to write their output, the
+                    // heartbeat would otherwise reload as soon as it sees the new timestamp
+                    state.ignoreTimestampChange = true;
+                    setTimeout(() => handleChangeEvents(payload, state), payload.delay);
+                } else {
+                    handleChangeEvents(payload, state);
+                }
+                break;
+            case 'resync':
+                console.log('[Vigil] Missed changes while disconnected, reloading...');
+                window.location.reload();
+                break;
+            case 'rate-limited':
+                showRateLimited(payload.limit);
+                break;
+            case 'config-reloaded':
+                // Settings are applied on the server, nothing on the page needs to change
+                console.log('[Vigil] Configuration reloaded from Catalyst.toml');
+                break;
+            case 'error':
+                console.error(`[Vigil] ${payload.message}`);
+                break;
+            case 'shutdown':
+                waitForRestart();
+                break;
+            case 'healthcheck':
+                console.log(`[Vigil] Server up for ${payload.uptime_secs}s, ${payload.total_reloads} reloads sent, ${payload.connected_clients} clients connected`);
+                break;
+            default:
+                console.debug('[Vigil] Ignoring unknown message type:', payload.type);
+        }
+    }
+    
+    // Handle a message from the server, shared by the WebSocket and SSE transports. This script asks
+    // for protocol 1, the plain `time:`/`reload:`/`css-swap:`/`rebuild:` strings are still understood
+    // for older servers.
+    function handleMessage(message, state) {
+        if (message.startsWith('{')) {
+            try {
+                const payload = JSON.parse(message);
+                if (payload.type) {
+                    handleProtocolMessage(payload, state);
+                } else {
+                    handleChangeEvents(payload, state);
+                }
+            } catch (e) {
+                console.warn('[Vigil] Could not parse change message:', message);
+            }
+        } else if (message.startsWith('time:')) {
+            handleTimestamp(parseInt(message.substring(5), 10), state);
+        } else if (message.startsWith('reload:')) {
+            // Process direct reload message
+            const filePath = message.substring(7);
+            if (filePath.startsWith('asset:')) {
+                applyAssetChanges(parseFileList(filePath.substring(6)), state);
+                return;
+            } else if (filePath.startsWith('i18n:')) {
+                console.log(`[Vigil] Translations changed: ${parseFileList(filePath.substring(5)).join(', ')}, reloading...`);
+            } else if (filePath.startsWith('renamed:')) {
+                const [oldPath, newPath] = parseFileList(filePath.substring(8));
+                console.log(`[Vigil] File renamed: ${oldPath} -> ${newPath}, reloading...`);
+            } else if (filePath.startsWith('deleted:')) {
+                console.log(`[Vigil] File deleted: ${parseFileList(filePath.substring(8)).join(', ')}, reloading...`);
+            } else if (filePath.startsWith('created:')) {
+                console.log(`[Vigil] File created: ${parseFileList(filePath.substring(8)).join(', ')}, reloading...`);
+            } else {
+                const filePaths = parseFileList(filePath);
+                console.log(`[Vigil] ${filePaths.length > 1 ? 'Files' : 'File'} changed: ${filePaths.join(', ')}, reloading...`);
+            }
+            window.location.reload();
+        } else if (message.startsWith('reload-batch:')) {
+            // Several files from one manual reload request, still a single page reload
+            const filePaths = parseFileList(message.substring(13));
+            console.log(`[Vigil] ${filePaths.length} files changed: ${filePaths.join(', ')}, reloading...`);
+            window.location.reload();
+        } else if (message.startsWith('css-swap:')) {
+            applyStylesheetChanges(parseFileList(message.substring(9)), state);
+        } else if (message.startsWith('rebuild:')) {
+            startRebuild(parseFileList(message.substring(8))[0]);
+        } else if (message === 'shutdown') {
+            waitForRestart();
+        } else if (message === 'rate-limited') {
+            showRateLimited();
+        } else if (message === 'config-reloaded') {
+            // Settings are applied on the server, nothing on the page needs to change
+            console.log('[Vigil] Configuration reloaded from Catalyst.toml');
+        } else if (message.startsWith('connected:')) {
+            // Process connection ID
+            state.connectionId = message.substring(10);
+            console.log(`[Vigil] Connected [id=${state.connectionId}]`);
+        }
+    }
     
     function connectWebSocket() {
         if (reconnectTimer) {
@@ -27,48 +434,18 @@
         }
         
         // Create WebSocket connection
-        const ws = new WebSocket(`ws://${window.location.host}/ws/dev/reload`);
+        const since = lastSequence === null ? '' : `&since=${lastSequence}`;
+        const ws = new WebSocket(`${socketUrl}?protocol=${PROTOCOL_VERSION}${since}${tokenParam}`, [WEBSOCKET_PROTOCOL]);
+        currentSocket = ws;
         
         // Connection tracking
         let lastResponseTime = Date.now();
-        let connectionId = null;
-        let lastChangeTimestamp = 0;
-        
-        // Set up ping interval (every second)
-        const pingInterval = setInterval(() => {
-            if (ws.readyState === WebSocket.OPEN) {
-                ws.send('ping');
-            }
-        }, 1000);
+        const state = { connectionId: null, lastChangeTimestamp: 0, ignoreTimestampChange: false };
         
         // Message handler
         ws.addEventListener('message', (event) => {
-            const message = event.data;
             lastResponseTime = Date.now();
-            
-            if (message.startsWith('time:')) {
-                // Process timestamp message
-                const serverTimestamp = parseInt(message.substring(5), 10);
-                
-                if (serverTimestamp > lastChangeTimestamp) {
-                    if (lastChangeTimestamp > 0) {
-                        console.log(`[Vigil] File changes detected, reloading...`);
-                        window.location.reload();
-                    } else {
-                        console.debug(`[Vigil] Initial timestamp: ${serverTimestamp}`);
-                    }
-                    lastChangeTimestamp = serverTimestamp;
-                }
-            } else if (message.startsWith('reload:')) {
-                // Process direct reload message
-                const filePath = message.substring(7);
-                console.log(`[Vigil] File changed: ${filePath}, reloading...`);
-                window.location.reload();
-            } else if (message.startsWith('connected:')) {
-                // Process connection ID
-                connectionId = message.substring(10);
-                console.log(`[Vigil] Connected [id=${connectionId}]`);
-            }
+            handleMessage(event.data, state);
         });
         
         // Health check (every 5 seconds)
@@ -76,39 +453,183 @@
             if (Date.now() - lastResponseTime > 10000) {
                 console.warn(`[Vigil] Connection timeout, reconnecting...`);
                 clearInterval(healthCheckInterval);
-                clearInterval(pingInterval);
                 try { ws.close(1001, "No response"); } catch (e) {}
                 attemptReconnect();
             }
         }, 5000);
         
         // Handle connection close
-        ws.addEventListener('close', () => {
+        ws.addEventListener('close', (event) => {
             clearInterval(healthCheckInterval);
-            clearInterval(pingInterval);
+            if (event.code === CLOSE_PROTOCOL_ERROR) {
+                console.error(`[Vigil] Server closed the connection: ${event.reason || 'protocol error'}, not reconnecting`);
+                showStatusOverlay('Hot reload unavailable, see console');
+                return;
+            }
+            if (SLOW_RETRY_CLOSE_CODES.includes(event.code)) {
+                console.warn(`[Vigil] Server closed the connection: ${event.reason}, retrying later`);
+                reconnectAttempts = Math.max(reconnectAttempts, 4);
+            }
             if (!isReconnecting) {
                 attemptReconnect();
             }
         });
         
-        // Silent error handling
+        // Silent error handling, a close event always follows and schedules the reconnect
         ws.addEventListener('error', () => {});
         
         // On open handler
         ws.addEventListener('open', () => {
+            webSocketOpened = true;
+            
+            // The server came back after a rebuild, pick up the new binary
+            if (rebuildPending && reconnectAttempts > 0) {
+                console.log('[Vigil] Server restarted, reloading...');
+                window.location.reload();
+                return;
+            }
+            
+            sendSubscription(ws);
             reconnectAttempts = 0;
             isReconnecting = false;
+            hideStatusOverlay();
             console.log('[Vigil] Connected to hot reload service');
         });
         
         return ws;
     }
     
+    // Fallback transport for environments that strip WebSocket upgrades
+    function connectEventSource() {
+        console.warn('[Vigil] WebSocket unavailable, falling back to Server-Sent Events');
+        
+        const source = new EventSource(`${routePrefix}/vigil/events?protocol=${PROTOCOL_VERSION}${tokenParam}`);
+        const state = { connectionId: null, lastChangeTimestamp: 0, ignoreTimestampChange: false };
+        let opened = false;
+        
+        // Proxies that buffer streaming responses never deliver the first message, poll instead
+        const connectedTimeout = setTimeout(() => {
+            if (state.connectionId === null) {
+                source.close();
+                startLongPolling();
+            }
+        }, 5000);
+        
+        source.addEventListener('message', (event) => handleMessage(event.data, state));
+        source.addEventListener('error', () => {
+            if (!rebuildPending) {
+                showStatusOverlay('Reconnecting…');
+            }
+        });
+        
+        // EventSource reconnects on its own, reload if the server came back after a rebuild
+        source.addEventListener('open', () => {
+            if (opened && rebuildPending) {
+                console.log('[Vigil] Server restarted, reloading...');
+                window.location.reload();
+                return;
+            }
+            opened = true;
+            hideStatusOverlay();
+            console.log('[Vigil] Connected to hot reload service (SSE)');
+        });
+        
+        return source;
+    }
+    
+    // Last resort when no streaming response gets through: each request waits on the server until
+    // a reload newer than the last one seen, or answers empty after a while, and the next one follows
+    function startLongPolling() {
+        console.warn('[Vigil] Streaming responses unavailable, falling back to long polling');
+        const state = { connectionId: null, lastChangeTimestamp: 0, ignoreTimestampChange: false };
+        let failures = 0;
+        
+        const poll = () => {
+            const since = lastSequence === null ? '' : `since=${lastSequence}`;
+            fetch(`${routePrefix}/vigil/poll?${since}${tokenParam}`, { cache: 'no-store' })
+                .then((response) => {
+                    if (!response.ok) {
+                        throw new Error(`HTTP ${response.status}`);
+                    }
+                    return response.json();
+                })
+                .then((result) => {
+                    // The server came back after a rebuild or shutdown, pick up the new binary
+                    if (failures > 0 && rebuildPending) {
+                        console.log('[Vigil] Server restarted, reloading...');
+                        window.location.reload();
+                        return;
+                    }
+                    failures = 0;
+                    hideStatusOverlay();
+                    if (checkBootId(result.boot)) {
+                        return;
+                    }
+                    
+                    if (lastSequence === null) {
+                        lastSequence = result.latest_seq;
+                    }
+                    result.messages.forEach((message) => handleProtocolMessage(message, state));
+                    poll();
+                })
+                .catch(() => {
+                    failures++;
+                    if (!rebuildPending) {
+                        showStatusOverlay('Reconnecting…');
+                    }
+                    setTimeout(poll, Math.min(500 * Math.pow(2, failures - 1), 16000));
+                });
+        };
+        
+        poll();
+    }
+    
+    // Tell the server which paths this page cares about, every change if no subscription is set
+    function sendSubscription(ws) {
+        if (subscriptionPatterns && ws.readyState === WebSocket.OPEN) {
+            ws.send(JSON.stringify({ type: 'subscribe', paths: subscriptionPatterns }));
+        }
+    }
+    
+    // Pages only interested in some files can call e.g. Vigil.subscribe(['templates/admin/**'])
+    window.Vigil = {
+        subscribe(patterns) {
+            subscriptionPatterns = Array.isArray(patterns) ? patterns : [patterns];
+            if (currentSocket) {
+                sendSubscription(currentSocket);
+            }
+        },
+        // Ask the server for its stats, logged to the console once the answer arrives
+        healthcheck() {
+            if (currentSocket && currentSocket.readyState === WebSocket.OPEN) {
+                currentSocket.send(JSON.stringify({ type: 'healthcheck' }));
+            } else {
+                console.warn('[Vigil] No open WebSocket to send a healthcheck on');
+            }
+        },
+    };
+    
     function attemptReconnect() {
         isReconnecting = true;
         reconnectAttempts++;
-        // Exponential backoff with 5 second maximum
-        const delay = Math.min(500 * Math.pow(1.5, reconnectAttempts - 1), 5000);
+        
+        // Give up on WebSockets if they never connected after the configured retries
+        if (!webSocketOpened && reconnectAttempts > sseFallbackRetries) {
+            if (window.EventSource) {
+                connectEventSource();
+            } else {
+                startLongPolling();
+            }
+            return;
+        }
+        
+        // Keep the rebuild overlay up while waiting for a restart, the reconnect is expected then
+        if (!rebuildPending) {
+            showStatusOverlay('Reconnecting…');
+        }
+        
+        // Exponential backoff starting at 500ms, doubling up to 16 seconds
+        const delay = Math.min(500 * Math.pow(2, reconnectAttempts - 1), 16000);
         reconnectTimer = setTimeout(connectWebSocket, delay);
     }
     
@@ -116,4 +637,4 @@
     connectWebSocket();
     
     console.log('%c[Vigil] Hot reload enabled', 'color: #8c16a1; font-weight: bold;');
-})();
+})();
\ No newline at end of file
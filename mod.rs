@@ -2,24 +2,63 @@ use crate::cata_log;
 use crate::services::makeuse;
 use crate::services::sparks::registry::Spark;
 use rocket::fairing::{Fairing, Info, Kind};
-use rocket::http::{ContentType, Header};
 use rocket::request::Request;
-use rocket::response::content::RawJavaScript;
 use rocket::response::Response;
-use rocket::{get, routes, Build, Rocket};
+use rocket::{Build, Rocket};
+use serde::{Serialize, Serializer};
+use std::collections::HashSet;
+use std::fmt;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, OnceLock, RwLock};
+
+// Only the development routes and the watcher behind them use these, and release builds leave both out
+#[cfg(debug_assertions)]
+use base64::Engine;
+#[cfg(debug_assertions)]
+use ignore::gitignore::Gitignore;
+#[cfg(debug_assertions)]
+use notify::event::{ModifyKind, RenameMode};
+#[cfg(debug_assertions)]
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+#[cfg(debug_assertions)]
+use rocket::futures::StreamExt;
+#[cfg(debug_assertions)]
+use rocket::http::{ContentType, Header, Status};
+#[cfg(debug_assertions)]
+use rocket::request::{FromRequest, Outcome};
+#[cfg(debug_assertions)]
+use rocket::response::content::RawJavaScript;
+#[cfg(debug_assertions)]
+use rocket::response::stream::{Event, EventStream};
+#[cfg(debug_assertions)]
+use rocket::{catch, catchers, get, post, routes, Responder, Shutdown};
+#[cfg(debug_assertions)]
+use rocket::tokio::sync::{broadcast, mpsc};
+#[cfg(debug_assertions)]
+use rocket_ws::frame::{CloseCode, CloseFrame};
+#[cfg(debug_assertions)]
 use rocket_ws::Message;
+#[cfg(debug_assertions)]
 use rocket_ws::WebSocket;
-use std::fs;
-use std::path::Path;
-use std::sync::atomic::{AtomicU64, Ordering};
-use std::sync::OnceLock;
-use std::time::UNIX_EPOCH;
-use std::time::{Instant, SystemTime};
+#[cfg(debug_assertions)]
+use serde::Deserialize;
+#[cfg(debug_assertions)]
+use std::collections::{HashMap, VecDeque};
+#[cfg(debug_assertions)]
+use std::sync::atomic::{AtomicU32, AtomicU64};
+#[cfg(debug_assertions)]
+use std::time::{SystemTime, UNIX_EPOCH};
+#[cfg(debug_assertions)]
+use subtle::ConstantTimeEq;
 
 // JS script for client-side hot reloading
+#[cfg(debug_assertions)]
 const DEV_RELOAD_JS: &str = include_str!("dev-reload.js");
 
 // Script injector that ensures our script is loaded
+#[cfg(debug_assertions)]
 const SCRIPT_INJECTOR_JS: &str = r#"
 // Vigil script injector
 (function() {
@@ -35,384 +74,3409 @@ const SCRIPT_INJECTOR_JS: &str = r#"
 })();
 "#;
 
+// Directory Catalyst serves static files from, at the root of the site
+#[cfg(debug_assertions)]
+const PUBLIC_DIR: &str = "public";
+
+// Path of the reload script below `route_prefix`
+#[cfg(debug_assertions)]
+const DEV_RELOAD_SCRIPT_PATH: &str = "/vigil/dev-reload.js";
+
 // Manifest for the spark
 const MANIFEST_TOML: &str = include_str!("manifest.toml");
 
-// Module for template watching in development mode
+// Directories watched for changes unless overridden by `watch_dirs`
+const WATCH_DIRS: [&str; 4] = [
+    "templates",  // Template files
+    "public/css", // CSS files
+    "public/js",  // JavaScript files
+    "src/assets", // Source assets (SCSS, TS, etc.)
+];
+
+// Swap, backup and lock files editors write next to the file being edited. Vim also probes
+// whether a directory is writable by creating a file named 4913.
+#[cfg(debug_assertions)]
+const EDITOR_TEMP_SUFFIXES: [&str; 3] = [".swp", ".swx", "~"];
+#[cfg(debug_assertions)]
+const EDITOR_TEMP_PREFIXES: [&str; 1] = [".#"];
+#[cfg(debug_assertions)]
+const EDITOR_TEMP_NAMES: [&str; 1] = ["4913"];
+
+// Keys accepted in the [spark.vigil] section of Catalyst.toml, grouped by expected type
+const BOOL_CONFIG_KEYS: [&str; 14] = ["enabled", "template_hot_reload", "body_injection", "watch_rust_sources", "hash_check", "respect_gitignore", "follow_symlinks", "watch_hidden", "parallel_scan", "dry_run", "partial_reload", "css_hot_swap", "allow_remote", "trust_proxy_headers"];
+const INTEGER_CONFIG_KEYS: [&str; 14] = ["refresh_interval", "debounce_ms", "cooldown_period", "sse_fallback_retries", "max_depth", "watch_depth", "ping_interval", "keepalive_interval", "max_watched_files", "max_watch_file_size_kb", "max_ws_connections", "max_errors", "long_poll_timeout", "reload_delay"];
+const STRING_LIST_CONFIG_KEYS: [&str; 13] = ["extra_watch_dirs", "watch_files", "exclude_dirs", "gitignore_overrides", "extensions", "asset_extensions", "ignore", "ignore_paths", "watch_include_patterns", "watch_exclude_patterns", "allowed_origins", "cors_origins", "allowed_ips"];
+const STRING_CONFIG_KEYS: [&str; 9] = ["log_level", "locales_dir", "sentinel_file", "change_detection", "vigil_api_username", "vigil_api_password", "dev_token", "public_ws_url", "route_prefix"];
+
+// Scans faster than this keep a core busy for little gain
+const MIN_REFRESH_INTERVAL_MS: i64 = 50;
+
+// Longest accepted interval; anything above is almost certainly a typo or a negative value that wrapped
+const MAX_INTERVAL_MS: u32 = 3_600_000;
+
+// Directories never descended into, extended by `exclude_dirs`
+const EXCLUDE_DIRS: [&str; 4] = ["node_modules", "target", ".git", "dist"];
+
+// File extensions that trigger a reload unless overridden by `extensions`
+const WATCH_EXTENSIONS: [&str; 6] = ["tera", "html", "css", "scss", "js", "ts"];
+
+// Extensions used to classify changed files for logging and reload handling
+#[cfg(debug_assertions)]
+const TEMPLATE_EXTENSIONS: [&str; 8] = ["tera", "html", "htm", "hbs", "handlebars", "jinja", "j2", "liquid"];
+#[cfg(debug_assertions)]
+const STYLESHEET_EXTENSIONS: [&str; 4] = ["css", "scss", "sass", "less"];
+#[cfg(debug_assertions)]
+const SCRIPT_EXTENSIONS: [&str; 6] = ["js", "ts", "mjs", "cjs", "jsx", "tsx"];
+
+// Images and fonts that can be refreshed in place, unless overridden by `asset_extensions`
+const ASSET_EXTENSIONS: [&str; 7] = ["png", "jpg", "jpeg", "webp", "svg", "woff2", "ico"];
+
+// Translation catalogs, watched inside `locales_dir` whatever the extension list says
+#[cfg(debug_assertions)]
+const TRANSLATION_EXTENSIONS: [&str; 3] = ["ftl", "po", "json"];
+
+// Directory containing Rust sources, watched when `watch_rust_sources` is enabled
+const RUST_SOURCE_DIR: &str = "src";
+
+// Capacity of the filesystem event queue feeding the shared change detector
+#[cfg(debug_assertions)]
+const EVENT_QUEUE_CAPACITY: usize = 256;
+
+// Maximum number of cached content hashes before the cache is cleared
+#[cfg(debug_assertions)]
+const HASH_CACHE_CAPACITY: usize = 10_000;
+
+// Directory listings are only reused once the directory has been unchanged for this long, since
+// coarse directory timestamps can't tell apart two changes within the same tick
+#[cfg(debug_assertions)]
+const DIRECTORY_LISTING_SETTLE_MS: u64 = 2000;
+
+// Milliseconds between scans while `max_watched_files` is exceeded
+#[cfg(debug_assertions)]
+const WATCH_LIMIT_RETRY_MS: u64 = 10_000;
+
+// Shortest time between two log lines about requests with a wrong dev_token
+#[cfg(debug_assertions)]
+const TOKEN_REJECTION_LOG_MS: u64 = 10_000;
+
+// Longest wait between scans while they keep failing
+#[cfg(debug_assertions)]
+const SCAN_BACKOFF_CAP_MS: u64 = 30_000;
+
+// Number of file changes kept for the /vigil/status change log
+#[cfg(debug_assertions)]
+const CHANGE_LOG_CAPACITY: usize = 50;
+
+// Most files listed by /vigil/api/watched-files
+#[cfg(debug_assertions)]
+const WATCHED_FILES_LIMIT: usize = 10_000;
+
+// Number of reload messages buffered for clients that fall behind
+#[cfg(debug_assertions)]
+const CHANGE_BROADCAST_CAPACITY: usize = 16;
+
+// Number of reload messages kept for clients that reconnect after missing them
+#[cfg(debug_assertions)]
+const REPLAY_CAPACITY: usize = 100;
+
+// Clients connecting with `?protocol=1` receive every message as a tagged JSON object
+#[cfg(debug_assertions)]
+const PROTOCOL_VERSION: u32 = 1;
+
+// WebSocket sub-protocol for protocol 1, confirmed in the upgrade response when requested
+#[cfg(debug_assertions)]
+const WEBSOCKET_PROTOCOL: &str = "vigil-v1";
+
+// Milliseconds between heartbeat messages while waiting for filesystem events
+#[cfg(debug_assertions)]
+const HEARTBEAT_INTERVAL_MS: u64 = 1000;
+
+// Reload messages published by the shared change detector to every connected client
+#[cfg(debug_assertions)]
+static CHANGE_BROADCAST: OnceLock<broadcast::Sender<VigilMessage>> = OnceLock::new();
+
+// Latest modification time seen, in milliseconds since the epoch (polling fallback only)
+#[cfg(debug_assertions)]
 static LAST_MOD_TIME: AtomicU64 = AtomicU64::new(0);
 
-// Global instance to expose settings
-static VIGIL_INSTANCE: OnceLock<VigilSpark> = OnceLock::new();
+// When the last reload was dispatched, in milliseconds since the epoch
+#[cfg(debug_assertions)]
+static LAST_RELOAD_TIME: AtomicU64 = AtomicU64::new(0);
 
-#[derive(Clone)]
+// Identifier handed to the next WebSocket or SSE connection
+#[cfg(debug_assertions)]
+static NEXT_CONNECTION_ID: AtomicU32 = AtomicU32::new(1);
+
+// Open WebSocket connections, limited by `max_ws_connections`
+#[cfg(debug_assertions)]
+static WS_CONNECTIONS: AtomicU32 = AtomicU32::new(0);
+
+// Streaming WebSocket clients by connection id, listed on the status page
+#[cfg(debug_assertions)]
+static CONNECTED_CLIENTS: Mutex<Option<HashMap<u32, ConnectedClient>>> = Mutex::new(None);
+
+// Whether directories cut off by `max_depth` have been reported
+#[cfg(debug_assertions)]
+static TRUNCATION_REPORTED: AtomicBool = AtomicBool::new(false);
+
+// Whether a file skipped for exceeding `max_watch_file_size_kb` has been reported
+#[cfg(debug_assertions)]
+static LARGE_FILE_REPORTED: AtomicBool = AtomicBool::new(false);
+
+// Whether the last scan was aborted because it found more than `max_watched_files` files
+#[cfg(debug_assertions)]
+static WATCH_LIMIT_EXCEEDED: AtomicBool = AtomicBool::new(false);
+
+// Polling scans that failed or timed out in a row, and the wait before the next one (0 once one succeeds)
+#[cfg(debug_assertions)]
+static SCAN_ERRORS: AtomicU32 = AtomicU32::new(0);
+#[cfg(debug_assertions)]
+static SCAN_BACKOFF_MS: AtomicU64 = AtomicU64::new(0);
+
+// Requests rejected for a wrong dev_token since the last log line about them, and when that was
+#[cfg(debug_assertions)]
+static TOKEN_REJECTIONS: AtomicU64 = AtomicU64::new(0);
+#[cfg(debug_assertions)]
+static TOKEN_REJECTION_LOGGED_AT: AtomicU64 = AtomicU64::new(0);
+
+// Number of files found by the last polling scan, None while native events are used
+#[cfg(debug_assertions)]
+static WATCHED_FILE_COUNT: Mutex<Option<usize>> = Mutex::new(None);
+
+// Directory listings from the previous scan, keyed by directory path (polling fallback only)
+#[cfg(debug_assertions)]
+static DIRECTORY_LISTINGS: Mutex<Option<HashMap<PathBuf, DirectoryListing>>> = Mutex::new(None);
+
+// Files seen during the previous scan with their modification times in milliseconds, used to
+// detect creations, deletions and modifications (polling fallback only)
+#[cfg(debug_assertions)]
+static KNOWN_FILES: Mutex<Option<HashMap<PathBuf, FileStamp>>> = Mutex::new(None);
+
+// Reload messages sent recently, oldest first, replayed to clients reconnecting with `?since=<seq>`
+#[cfg(debug_assertions)]
+static REPLAY_BUFFER: Mutex<VecDeque<VigilMessage>> = Mutex::new(VecDeque::new());
+
+// Highest sequence number that dropped out of the replay buffer
+#[cfg(debug_assertions)]
+static REPLAY_EVICTED: AtomicU64 = AtomicU64::new(0);
+
+// Sequence numbers count up from the server's start time in milliseconds, so numbers a client
+// kept from before a restart are lower than any the running server hands out
+#[cfg(debug_assertions)]
+static SEQUENCE_START: OnceLock<u64> = OnceLock::new();
+
+// Modification time of `sentinel_file` when it was last read
+#[cfg(debug_assertions)]
+static SENTINEL_MOD_TIME: AtomicU64 = AtomicU64::new(0);
+
+// When the spark was created, for the uptime reported to healthchecks
+static STARTED_AT: OnceLock<std::time::Instant> = OnceLock::new();
+
+// Identifies this server process, so clients reconnecting after a restart know to reload
+#[cfg(debug_assertions)]
+static BOOT_ID: OnceLock<String> = OnceLock::new();
+#[cfg(debug_assertions)]
+static SEQUENCES_ISSUED: AtomicU64 = AtomicU64::new(0);
+
+// Most recent file changes as (milliseconds since the epoch, path), newest last
+#[cfg(debug_assertions)]
+static CHANGE_LOG: Mutex<VecDeque<ChangeEvent>> = Mutex::new(VecDeque::new());
+
+// Content hashes of watched files, used when `hash_check` is enabled
+#[cfg(debug_assertions)]
+static CONTENT_HASHES: Mutex<Option<HashMap<PathBuf, [u8; 32]>>> = Mutex::new(None);
+
+// Parsed .gitignore files, deepest first, used when `respect_gitignore` is enabled
+#[cfg(debug_assertions)]
+static GITIGNORES: OnceLock<Vec<Gitignore>> = OnceLock::new();
+
+// Global instance to expose settings, kept from the first spark created until `VigilSpark::reset`
+static VIGIL_INSTANCE: RwLock<Option<VigilSpark>> = RwLock::new(None);
+
+// Configuration in use, replaced when Catalyst.toml changes
+static ACTIVE_CONFIG: RwLock<Option<VigilConfig>> = RwLock::new(None);
+
+// Callbacks run when translation files change, e.g. to drop cached catalogs
+type TranslationHook = Box<dyn Fn(&[PathBuf]) + Send>;
+static TRANSLATION_HOOKS: Mutex<Vec<TranslationHook>> = Mutex::new(Vec::new());
+
+// Whether the configuration was loaded from Catalyst.toml and should follow edits to it
+static FOLLOW_CATALYST_TOML: AtomicBool = AtomicBool::new(false);
+
+// Cumulative reload and scan statistics since startup
+#[cfg(debug_assertions)]
+static METRICS: VigilMetrics = VigilMetrics::new();
+
+#[derive(Clone, Debug, Serialize)]
 pub struct VigilSpark {
-    environment: String,
-    config: VigilConfig,
+    pub environment: String,
+    // Configuration the spark started with, later edits to Catalyst.toml aren't reflected here
+    #[serde(flatten)]
+    pub config: VigilConfig,
 }
 
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct VigilConfig {
+    pub template_hot_reload: bool,
+    pub body_injection: bool,
+    pub refresh_interval: u32,
+    pub debounce_ms: u32,
+    pub cooldown_period: u32,
+    // Milliseconds browsers wait before reloading, for build steps that finish after the save
+    pub reload_delay: u32,
+    pub sse_fallback_retries: u32,
+    // Milliseconds a /vigil/poll request waits for a change before answering empty
+    pub long_poll_timeout: u32,
+    pub ping_interval: u32,
+    // Open WebSocket connections allowed at once, 0 for unlimited
+    pub max_ws_connections: u32,
+    // Failed scans in a row before open pages are told, 0 to only log them
+    pub max_errors: u32,
+    pub watch_rust_sources: bool,
+    pub hash_check: bool,
+    // How polling decides a file changed
+    pub change_detection: ChangeDetection,
+    pub respect_gitignore: bool,
+    pub follow_symlinks: bool,
+    // Watch dotfiles and files inside dot-directories
+    pub watch_hidden: bool,
+    // Walk the watch directories on separate threads when polling
+    pub parallel_scan: bool,
+    // Log detected changes instead of sending them to browsers
+    pub dry_run: bool,
+    // Re-render template changes into the open page instead of reloading it
+    pub partial_reload: bool,
+    // Swap changed stylesheets into the open page instead of reloading it
+    pub css_hot_swap: bool,
+    // Directory levels scanned below each watch root, None for unlimited
+    pub max_depth: Option<u32>,
+    // Scans finding more files than this are aborted, 0 for unlimited
+    pub max_watched_files: u32,
+    // Files larger than this many KiB aren't watched, 0 for unlimited
+    pub max_watch_file_size_kb: u64,
+    pub gitignore_overrides: Vec<PathBuf>,
+    pub watch_dirs: Vec<WatchDirConfig>,
+    pub extra_watch_dirs: Vec<PathBuf>,
+    // Individual files watched regardless of the extension filter, e.g. tailwind.config.js
+    pub watch_files: Vec<PathBuf>,
+    // Translation catalogs, watched in addition to the watch directories when present
+    pub locales_dir: PathBuf,
+    // File an external watcher such as cargo-watch writes changed paths into, one per line. When
+    // set, only this file is polled and the watch directories are never walked.
+    pub sentinel_file: Option<PathBuf>,
+    pub exclude_dirs: Vec<String>,
+    pub extensions: Vec<String>,
+    pub asset_extensions: Vec<String>,
+    #[serde(serialize_with = "serialize_patterns")]
+    pub ignore: Vec<glob::Pattern>,
+    // Like `ignore`, but a pattern matching a directory also skips everything below it
+    #[serde(serialize_with = "serialize_patterns")]
+    pub ignore_paths: Vec<glob::Pattern>,
+    #[serde(serialize_with = "serialize_patterns")]
+    pub watch_include_patterns: Vec<glob::Pattern>,
+    #[serde(serialize_with = "serialize_patterns")]
+    pub watch_exclude_patterns: Vec<glob::Pattern>,
+    // Origins allowed to open the reload WebSocket besides the server itself and localhost,
+    // e.g. "https://dev.example.test"
+    pub allowed_origins: Vec<String>,
+    // Origins, or glob patterns like "http://localhost:*", that may call the /vigil routes from
+    // another page, e.g. a frontend dev server on its own port. Empty sends no CORS headers.
+    pub cors_origins: Vec<String>,
+    // WebSocket URL browsers connect to, e.g. "wss://dev.example.test/app/ws/dev/reload" behind a
+    // TLS proxy. Empty for the page's own host, with wss:// on https pages.
+    pub public_ws_url: String,
+    // Path prefix a reverse proxy serves the app under, e.g. "/app", added to every URL the
+    // browser is given. Rocket still mounts the routes at the root.
+    pub route_prefix: String,
+    // Serve the /vigil routes and the reload script to other machines than this one
+    pub allow_remote: bool,
+    // Addresses or CIDR ranges, e.g. "192.168.1.0/24", served even without allow_remote
+    pub allowed_ips: Vec<String>,
+    // Take the client address from X-Forwarded-For, only behind a proxy that sets it
+    pub trust_proxy_headers: bool,
+    // Basic Auth credentials for the status and API routes, which are open unless both are set.
+    // Never serialized, so /vigil/api/config and the reload log don't reveal them.
+    #[serde(skip_serializing)]
+    pub vigil_api_username: Option<String>,
+    #[serde(skip_serializing)]
+    pub vigil_api_password: Option<ApiPassword>,
+    // Shared secret the reload connections and JSON endpoints require when set, handed to the
+    // reload script through the page it is injected into
+    #[serde(skip_serializing)]
+    pub dev_token: Option<ApiPassword>,
+}
+
+// Basic Auth password or dev token that is redacted in Debug output
 #[derive(Clone)]
-struct VigilConfig {
-    template_hot_reload: bool,
-    refresh_interval: u32,
-    cooldown_period: u32,
+pub struct ApiPassword(String);
+
+impl ApiPassword {
+    pub fn new(password: impl Into<String>) -> Self {
+        Self(password.into())
+    }
+
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
 }
 
-impl VigilSpark {
-    fn new() -> Self {
-        // Load environment setting from Catalyst.toml
-        let environment = Self::get_environment();
+impl fmt::Debug for ApiPassword {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ApiPassword(<redacted>)")
+    }
+}
 
-        // Load config from manifest.toml and Catalyst.toml
-        let config = Self::load_config();
+// Compared in constant time, like the checks against request headers
+impl PartialEq for ApiPassword {
+    fn eq(&self, other: &Self) -> bool {
+        bool::from(subtle::ConstantTimeEq::ct_eq(self.0.as_bytes(), other.0.as_bytes()))
+    }
+}
 
-        let instance = Self { environment, config };
+// One `key = value` line per setting, sorted by key, with values as they'd be written in JSON.
+// Credentials are summarized as `api_auth` and `dev_token` instead of being printed.
+impl fmt::Display for VigilConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Ok(serde_json::Value::Object(mut settings)) = serde_json::to_value(self) else {
+            return Err(fmt::Error);
+        };
+        settings.insert("api_auth".to_string(), serde_json::json!(self.vigil_api_username.is_some() && self.vigil_api_password.is_some()));
+        settings.insert("dev_token".to_string(), serde_json::json!(self.dev_token.is_some()));
 
-        // Store the instance for global access
-        let _ = VIGIL_INSTANCE.get_or_init(|| instance.clone());
+        let width = settings.keys().map(String::len).max().unwrap_or(0);
+        for (index, (key, value)) in settings.iter().enumerate() {
+            if index > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "  {:<width$} = {}", key, value, width = width)?;
+        }
+        Ok(())
+    }
+}
 
-        instance
+// The settings as they'd be written in [spark.vigil], credentials left out as in every other
+// serialized form. Unset optional settings are omitted.
+impl From<VigilConfig> for toml::Value {
+    fn from(config: VigilConfig) -> Self {
+        toml::Value::try_from(&config).unwrap_or_else(|_| toml::Value::Table(toml::value::Table::new()))
     }
+}
 
-    // Parse manifest.toml and Catalyst.toml for configuration
-    fn load_config() -> VigilConfig {
-        use std::env;
+impl VigilConfig {
+    // A Catalyst.toml document with these settings under [spark.vigil], e.g. to generate one
+    pub fn to_toml_string(&self) -> String {
+        let mut spark = toml::value::Table::new();
+        spark.insert("vigil".to_string(), toml::Value::from(self.clone()));
+        let mut document = toml::value::Table::new();
+        document.insert("spark".to_string(), toml::Value::Table(spark));
+        toml::to_string(&toml::Value::Table(document)).unwrap_or_default()
+    }
 
-        // Load and parse Catalyst.toml
-        let toml_config = Self::parse_catalyst_toml();
+    // Settings from the contents of a Catalyst.toml, falling back to environment variables and
+    // manifest.toml for keys it doesn't set, like the file itself
+    pub fn from_toml_str(text: &str) -> Result<Self, toml::de::Error> {
+        let toml = toml::from_str::<toml::Value>(text)?;
+        Ok(VigilSpark::config_from_toml(Some(toml)))
+    }
+}
 
-        // Default configuration values
-        let default_template_hot_reload = true;
-        let default_refresh_interval = 1000;
-        let default_cooldown_period = 3000;
+// How the polling scanner decides that a file changed
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChangeDetection {
+    // Modification time and size
+    Mtime,
+    // Content hash of every watched file on every scan, for filesystems whose modification times
+    // can't be trusted (Docker volumes on macOS, network mounts, FAT)
+    Hash,
+}
 
-        // Build config with cascading priority: Catalyst.toml -> env -> manifest.toml -> defaults
-        let template_hot_reload = Self::get_config_bool(
-            &toml_config,
-            "template_hot_reload",
-            "VIGIL_TEMPLATE_HOT_RELOAD",
-            Self::get_manifest_bool("template_hot_reload", default_template_hot_reload),
-        );
+impl ChangeDetection {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ChangeDetection::Mtime => "mtime",
+            ChangeDetection::Hash => "hash",
+        }
+    }
+}
 
-        let refresh_interval = Self::get_config_integer(&toml_config, "refresh_interval", "VIGIL_REFRESH_INTERVAL", Self::get_manifest_integer("refresh_interval", default_refresh_interval)) as u32;
+// A watched directory, optionally with its own extension list
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct WatchDirConfig {
+    pub path: PathBuf,
+    // Replaces the global `extensions` and `asset_extensions` inside this directory
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub extensions: Option<Vec<String>>,
+}
 
-        let cooldown_period = Self::get_config_integer(&toml_config, "cooldown_period", "VIGIL_COOLDOWN_PERIOD", Self::get_manifest_integer("cooldown_period", default_cooldown_period)) as u32;
+impl WatchDirConfig {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into(), extensions: None }
+    }
+}
 
-        cata_log!(
-            Info,
-            format!(
-                "Vigil config loaded: template_hot_reload={}, refresh_interval={}ms, cooldown_period={}ms",
-                template_hot_reload, refresh_interval, cooldown_period
-            )
-        );
+// Serialize compiled glob patterns as their source strings
+fn serialize_patterns<S: Serializer>(patterns: &[glob::Pattern], serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_seq(patterns.iter().map(|pattern| pattern.as_str()))
+}
 
-        VigilConfig {
-            template_hot_reload,
-            refresh_interval,
-            cooldown_period,
+impl Default for VigilConfig {
+    // Defaults from manifest.toml, falling back to hardcoded values
+    fn default() -> Self {
+        let default_watch_dirs: Vec<String> = WATCH_DIRS.iter().map(|dir| dir.to_string()).collect();
+        let default_extensions: Vec<String> = WATCH_EXTENSIONS.iter().map(|ext| ext.to_string()).collect();
+        let default_exclude_dirs: Vec<String> = EXCLUDE_DIRS.iter().map(|dir| dir.to_string()).collect();
+
+        Self {
+            template_hot_reload: VigilSpark::get_manifest_bool("template_hot_reload", true),
+            body_injection: VigilSpark::get_manifest_bool("body_injection", true),
+            refresh_interval: VigilSpark::get_manifest_integer("refresh_interval", 1000) as u32,
+            debounce_ms: VigilSpark::get_manifest_integer("debounce_ms", 300) as u32,
+            cooldown_period: VigilSpark::get_manifest_integer("cooldown_period", 3000) as u32,
+            reload_delay: VigilSpark::get_manifest_integer("reload_delay", 0) as u32,
+            sse_fallback_retries: VigilSpark::get_manifest_integer("sse_fallback_retries", 3) as u32,
+            long_poll_timeout: VigilSpark::get_manifest_integer("long_poll_timeout", 25_000) as u32,
+            ping_interval: VigilSpark::get_manifest_integer("ping_interval", 15000) as u32,
+            max_ws_connections: VigilSpark::get_manifest_integer("max_ws_connections", 10) as u32,
+            max_errors: VigilSpark::get_manifest_integer("max_errors", 5) as u32,
+            watch_rust_sources: VigilSpark::get_manifest_bool("watch_rust_sources", false),
+            hash_check: VigilSpark::get_manifest_bool("hash_check", false),
+            change_detection: VigilSpark::parse_change_detection(&VigilSpark::get_manifest_string("change_detection", "mtime")),
+            respect_gitignore: VigilSpark::get_manifest_bool("respect_gitignore", false),
+            follow_symlinks: VigilSpark::get_manifest_bool("follow_symlinks", false),
+            watch_hidden: VigilSpark::get_manifest_bool("watch_hidden", false),
+            parallel_scan: VigilSpark::get_manifest_bool("parallel_scan", true),
+            dry_run: VigilSpark::get_manifest_bool("dry_run", false),
+            partial_reload: VigilSpark::get_manifest_bool("partial_reload", false),
+            css_hot_swap: VigilSpark::get_manifest_bool("css_hot_swap", true),
+            max_depth: u32::try_from(VigilSpark::get_manifest_integer("max_depth", -1)).ok(),
+            max_watched_files: VigilSpark::get_manifest_integer("max_watched_files", 20_000) as u32,
+            max_watch_file_size_kb: VigilSpark::get_manifest_integer("max_watch_file_size_kb", 0) as u64,
+            gitignore_overrides: VigilSpark::get_manifest_string_list("gitignore_overrides", Vec::new()).into_iter().map(PathBuf::from).collect(),
+            watch_dirs: VigilSpark::get_manifest_string_list("watch_dirs", default_watch_dirs).into_iter().map(WatchDirConfig::new).collect(),
+            extra_watch_dirs: VigilSpark::get_manifest_string_list("extra_watch_dirs", Vec::new()).into_iter().map(PathBuf::from).collect(),
+            watch_files: VigilSpark::get_manifest_string_list("watch_files", Vec::new()).into_iter().map(PathBuf::from).collect(),
+            locales_dir: PathBuf::from(VigilSpark::get_manifest_string("locales_dir", "locales")),
+            sentinel_file: Some(VigilSpark::get_manifest_string("sentinel_file", "")).filter(|file| !file.is_empty()).map(PathBuf::from),
+            exclude_dirs: VigilSpark::merge_exclude_dirs(default_exclude_dirs, VigilSpark::get_manifest_string_list("exclude_dirs", Vec::new())),
+            extensions: VigilSpark::normalize_extensions(VigilSpark::get_manifest_string_list("extensions", default_extensions)),
+            asset_extensions: VigilSpark::normalize_extensions(VigilSpark::get_manifest_string_list("asset_extensions", ASSET_EXTENSIONS.iter().map(|ext| ext.to_string()).collect())),
+            ignore: VigilSpark::compile_patterns(&VigilSpark::get_manifest_string_list("ignore", Vec::new())),
+            ignore_paths: VigilSpark::compile_patterns(&VigilSpark::get_manifest_string_list("ignore_paths", Vec::new())),
+            watch_include_patterns: VigilSpark::compile_patterns(&VigilSpark::get_manifest_string_list("watch_include_patterns", Vec::new())),
+            watch_exclude_patterns: VigilSpark::compile_patterns(&VigilSpark::get_manifest_string_list("watch_exclude_patterns", Vec::new())),
+            allowed_origins: VigilSpark::get_manifest_string_list("allowed_origins", Vec::new()),
+            cors_origins: VigilSpark::get_manifest_string_list("cors_origins", Vec::new()),
+            public_ws_url: VigilSpark::get_manifest_string("public_ws_url", ""),
+            route_prefix: VigilSpark::normalize_route_prefix(&VigilSpark::get_manifest_string("route_prefix", "")),
+            allow_remote: VigilSpark::get_manifest_bool("allow_remote", false),
+            allowed_ips: VigilSpark::get_manifest_string_list("allowed_ips", Vec::new()),
+            trust_proxy_headers: VigilSpark::get_manifest_bool("trust_proxy_headers", false),
+            vigil_api_username: Some(VigilSpark::get_manifest_string("vigil_api_username", "")).filter(|username| !username.is_empty()),
+            vigil_api_password: Some(VigilSpark::get_manifest_string("vigil_api_password", "")).filter(|password| !password.is_empty()).map(ApiPassword),
+            dev_token: Some(VigilSpark::get_manifest_string("dev_token", "")).filter(|token| !token.is_empty()).map(ApiPassword),
         }
     }
+}
 
-    // Parse Catalyst.toml file
-    fn parse_catalyst_toml() -> Option<toml::Value> {
-        use std::fs;
+// Files changed since the last check
+#[cfg(debug_assertions)]
+#[derive(Clone, Default)]
+struct DetectedChanges {
+    modified: Vec<PathBuf>,
+    created: Vec<PathBuf>,
+    deleted: Vec<PathBuf>,
+    // Pairs of (old path, new path)
+    renamed: Vec<(PathBuf, PathBuf)>,
+}
 
-        let config_path = "Catalyst.toml";
-        let config_str = fs::read_to_string(config_path).unwrap_or_else(|_| {
-            cata_log!(Warning, "Could not find Catalyst.toml, using default configuration");
-            String::new()
-        });
+#[cfg(debug_assertions)]
+impl DetectedChanges {
+    fn is_empty(&self) -> bool {
+        self.modified.is_empty() && self.created.is_empty() && self.deleted.is_empty() && self.renamed.is_empty()
+    }
 
-        if !config_str.is_empty() {
-            match toml::from_str::<toml::Value>(&config_str) {
-                Ok(config) => Some(config),
-                Err(e) => {
-                    cata_log!(Error, format!("Failed to parse Catalyst.toml: {}", e));
-                    None
+    // Add changes detected later in the same debounce window, skipping files already listed
+    fn merge(&mut self, other: DetectedChanges) {
+        fn extend_unique(files: &mut Vec<PathBuf>, other_files: Vec<PathBuf>) {
+            for file in other_files {
+                if !files.contains(&file) {
+                    files.push(file);
                 }
             }
-        } else {
-            None
+        }
+
+        extend_unique(&mut self.modified, other.modified);
+        extend_unique(&mut self.created, other.created);
+        extend_unique(&mut self.deleted, other.deleted);
+
+        for rename in other.renamed {
+            if !self.renamed.contains(&rename) {
+                self.renamed.push(rename);
+            }
         }
     }
+}
 
-    // Get boolean value from manifest.toml config.defaults section
-    fn get_manifest_bool(key: &str, default: bool) -> bool {
-        if let Ok(manifest) = toml::from_str::<toml::Value>(MANIFEST_TOML) {
-            // Check in config.defaults section
-            if let Some(config) = manifest.get("config") {
-                if let Some(defaults) = config.get("defaults") {
-                    if let Some(value) = defaults.get(key) {
-                        if let Some(bool_value) = value.as_bool() {
-                            return bool_value;
-                        }
-                    }
-                }
+#[cfg(debug_assertions)]
+impl DetectedChanges {
+    // Flatten into one event per file, renames and removals first
+    fn events(&self, timestamp: u64) -> Vec<ChangeEvent> {
+        // Native events report absolute paths, which mean nothing to the browser
+        let event = |path: &PathBuf, kind: ChangeKind| {
+            let path = VigilSpark::relative_path(path);
+            ChangeEvent {
+                url: VigilSpark::public_url(&path),
+                category: VigilSpark::file_type(&path),
+                path,
+                kind,
+                timestamp,
+                previous_path: None,
             }
+        };
 
-            // Also check at root level for backward compatibility
-            if let Some(value) = manifest.get(key) {
-                if let Some(bool_value) = value.as_bool() {
-                    return bool_value;
-                }
+        let renamed = self.renamed.iter().map(|(old_path, new_path)| ChangeEvent {
+            previous_path: Some(VigilSpark::relative_path(old_path)),
+            ..event(new_path, ChangeKind::Renamed)
+        });
+        renamed
+            .chain(self.deleted.iter().map(|path| event(path, ChangeKind::Deleted)))
+            .chain(self.created.iter().map(|path| event(path, ChangeKind::Created)))
+            .chain(self.modified.iter().map(|path| event(path, ChangeKind::Modified)))
+            .collect()
+    }
+}
+
+// How a watched file changed
+#[cfg(debug_assertions)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum ChangeKind {
+    Created,
+    Modified,
+    Deleted,
+    Renamed,
+}
+
+#[cfg(debug_assertions)]
+impl ChangeKind {
+    fn as_str(&self) -> &'static str {
+        match self {
+            ChangeKind::Created => "created",
+            ChangeKind::Modified => "modified",
+            ChangeKind::Deleted => "deleted",
+            ChangeKind::Renamed => "renamed",
+        }
+    }
+}
+
+// A single change as sent to clients and shown on the status page
+#[cfg(debug_assertions)]
+#[derive(Clone, Debug, Serialize)]
+struct ChangeEvent {
+    // Relative to the project root
+    #[serde(serialize_with = "serialize_path")]
+    path: PathBuf,
+    // Where the browser loads the file from, for files under public/
+    #[serde(skip_serializing_if = "Option::is_none")]
+    url: Option<String>,
+    kind: ChangeKind,
+    // File type, e.g. "Stylesheet" or "Template"
+    category: &'static str,
+    // Milliseconds since the epoch
+    timestamp: u64,
+    // Path before a rename
+    #[serde(skip_serializing_if = "Option::is_none", serialize_with = "serialize_optional_path")]
+    previous_path: Option<PathBuf>,
+}
+
+#[cfg(debug_assertions)]
+impl ChangeEvent {
+    // Human-readable description for logs, e.g. "Stylesheet modified: public/css/site.css"
+    fn describe(&self) -> String {
+        match &self.previous_path {
+            Some(previous_path) => format!("{} {}: {} -> {}", self.category, self.kind.as_str(), previous_path.display(), self.path.display()),
+            None => format!("{} {}: {}", self.category, self.kind.as_str(), self.path.display()),
+        }
+    }
+}
+
+// A message to connected clients. Protocol 1 clients get it as JSON tagged with `type`, e.g.
+// `{"type":"connected","id":3,"seq":1714566600001,"boot":"18f3a2c4d10-4d2"}`, older clients the original string formats.
+#[cfg(debug_assertions)]
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+enum VigilMessage {
+    // `seq` is the sequence number of the latest reload, to pass as `since` when reconnecting
+    // `boot` changes whenever the server restarts
+    Connected { id: u32, seq: u64, boot: &'static str },
+    // Heartbeat with the latest modification time seen
+    Ping { timestamp: u64 },
+    // `action` is reload, css-swap, asset, i18n or rebuild
+    // `count` is the number of changed files, so clients can summarize large batches
+    // `delay` is how many milliseconds clients wait before acting on it
+    Reload { seq: u64, action: &'static str, count: usize, delay: u32, events: Vec<ChangeEvent> },
+    // Reloads the client missed are no longer buffered, it should reload the page once
+    Resync,
+    // Edits to Catalyst.toml were applied
+    ConfigReloaded,
+    // Sent before closing a connection that would exceed `max_ws_connections`
+    RateLimited { limit: u32 },
+    Error { message: String },
+    // Rocket is shutting down. Sent right before the connection closes, clients should reload once
+    // they can reconnect since anything may have changed during the restart.
+    Shutdown,
+    // Answer to a client's healthcheck, `server_time` in milliseconds since the epoch
+    Healthcheck { uptime_secs: u64, total_reloads: u64, connected_clients: usize, server_time: u64 },
+}
+
+#[cfg(debug_assertions)]
+impl VigilMessage {
+    fn to_text(&self, protocol: u32) -> String {
+        if protocol >= PROTOCOL_VERSION {
+            return serde_json::to_string(self).unwrap_or_default();
+        }
+
+        match self {
+            VigilMessage::Connected { id, .. } => format!("connected:{}", id),
+            VigilMessage::Ping { timestamp } => format!("time:{}", timestamp),
+            // The original client reloads the page on any `reload:` message and only logs the path,
+            // so the first changed file is enough
+            VigilMessage::Reload { events, .. } => format!("reload:{}", events.first().map(|event| wire_path(&event.path)).unwrap_or_else(|| String::from("manual"))),
+            VigilMessage::Resync => String::from("resync"),
+            VigilMessage::ConfigReloaded => String::from("config-reloaded"),
+            VigilMessage::RateLimited { .. } => String::from("rate-limited"),
+            VigilMessage::Error { message } => format!("error:{}", message),
+            VigilMessage::Shutdown => String::from("shutdown"),
+            // Only sent on request, so there is no older format to keep
+            VigilMessage::Healthcheck { .. } => serde_json::to_string(self).unwrap_or_default(),
+        }
+    }
+
+    // Drop the events of a reload that don't match a connection's subscription, and the whole reload
+    // if none are left. Other messages, and connections without a subscription, get everything.
+    fn filtered(self, filters: &[glob::Pattern]) -> Option<Self> {
+        match self {
+            VigilMessage::Reload { seq, action, delay, events, .. } if !filters.is_empty() => {
+                let matches = |path: &Path| filters.iter().any(|filter| filter.matches_path(&VigilSpark::relative_path(path)));
+                let events: Vec<ChangeEvent> = events.into_iter().filter(|event| matches(&event.path) || event.previous_path.as_deref().is_some_and(matches)).collect();
+                (!events.is_empty()).then_some(VigilMessage::Reload { seq, action, count: events.len(), delay, events })
             }
+            message => Some(message),
         }
-        default
     }
 
-    // Get integer value from manifest.toml config.defaults section
-    fn get_manifest_integer(key: &str, default: i64) -> i64 {
-        if let Ok(manifest) = toml::from_str::<toml::Value>(MANIFEST_TOML) {
-            // Check in config.defaults section
-            if let Some(config) = manifest.get("config") {
-                if let Some(defaults) = config.get("defaults") {
-                    if let Some(value) = defaults.get(key) {
-                        if let Some(int_value) = value.as_integer() {
-                            return int_value;
-                        }
+    // A reload message with the next sequence number
+    fn reload(action: &'static str, events: Vec<ChangeEvent>) -> Self {
+        let seq = Self::sequence_start() + SEQUENCES_ISSUED.fetch_add(1, Ordering::SeqCst) + 1;
+        let delay = VigilSpark::current_config().reload_delay;
+        VigilMessage::Reload { seq, action, count: events.len(), delay, events }
+    }
+
+    fn sequence_start() -> u64 {
+        *SEQUENCE_START.get_or_init(|| SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as u64)
+    }
+
+    // Start time and process id, unique enough to tell restarts of the dev server apart
+    fn boot_id() -> &'static str {
+        BOOT_ID.get_or_init(|| format!("{:x}-{:x}", SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis(), std::process::id()))
+    }
+
+    // Sequence number of the latest reload message
+    fn last_sequence() -> u64 {
+        Self::sequence_start() + SEQUENCES_ISSUED.load(Ordering::SeqCst)
+    }
+
+    // Keep a sent reload message for clients that reconnect after missing it
+    fn remember(&self) {
+        if !matches!(self, VigilMessage::Reload { .. }) {
+            return;
+        }
+        let Ok(mut buffer) = REPLAY_BUFFER.lock() else {
+            return;
+        };
+
+        buffer.push_back(self.clone());
+        if buffer.len() > REPLAY_CAPACITY {
+            if let Some(VigilMessage::Reload { seq, .. }) = buffer.pop_front() {
+                REPLAY_EVICTED.store(seq, Ordering::SeqCst);
+            }
+        }
+    }
+
+    // Messages for a client that last saw reload `since`: the reloads sent after it, or a resync when
+    // some of them are no longer buffered or `since` is from before a server restart
+    fn replay_since(since: u64) -> Vec<VigilMessage> {
+        if since >= Self::last_sequence() {
+            return Vec::new();
+        }
+        if since < Self::sequence_start() || since < REPLAY_EVICTED.load(Ordering::SeqCst) {
+            return vec![VigilMessage::Resync];
+        }
+
+        REPLAY_BUFFER
+            .lock()
+            .map(|buffer| buffer.iter().filter(|message| matches!(message, VigilMessage::Reload { seq, .. } if *seq > since)).cloned().collect())
+            .unwrap_or_default()
+    }
+}
+
+// Counters for judging whether hot reload keeps up, served by /vigil/api/metrics and /vigil/metrics
+#[cfg(debug_assertions)]
+pub struct VigilMetrics {
+    // Reload messages sent to browsers, including manual ones
+    total_reloads: AtomicU64,
+    // Completed polling scans, native file events don't scan
+    total_polls: AtomicU64,
+    total_poll_duration_ms: AtomicU64,
+}
+
+#[cfg(debug_assertions)]
+impl VigilMetrics {
+    const fn new() -> Self {
+        VigilMetrics {
+            total_reloads: AtomicU64::new(0),
+            total_polls: AtomicU64::new(0),
+            total_poll_duration_ms: AtomicU64::new(0),
+        }
+    }
+
+    fn record_reload(&self) {
+        self.total_reloads.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn record_poll(&self, duration_ms: u64) {
+        self.total_polls.fetch_add(1, Ordering::Relaxed);
+        self.total_poll_duration_ms.fetch_add(duration_ms, Ordering::Relaxed);
+    }
+
+    // Mean scan time in milliseconds, 0 before the first scan
+    pub fn average_poll_duration_ms(&self) -> f64 {
+        let polls = self.total_polls.load(Ordering::Relaxed);
+        if polls == 0 {
+            return 0.0;
+        }
+        self.total_poll_duration_ms.load(Ordering::Relaxed) as f64 / polls as f64
+    }
+
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "total_reloads": self.total_reloads.load(Ordering::Relaxed),
+            "total_polls": self.total_polls.load(Ordering::Relaxed),
+            "total_poll_duration_ms": self.total_poll_duration_ms.load(Ordering::Relaxed),
+            "average_poll_duration_ms": self.average_poll_duration_ms(),
+        })
+    }
+
+    // The same numbers in the Prometheus text exposition format
+    pub fn prometheus_text(&self) -> String {
+        let gauges = [
+            ("vigil_total_reloads", "Reload messages sent to browsers", self.total_reloads.load(Ordering::Relaxed) as f64),
+            ("vigil_total_polls", "Completed polling scans", self.total_polls.load(Ordering::Relaxed) as f64),
+            ("vigil_total_poll_duration_ms", "Milliseconds spent in polling scans", self.total_poll_duration_ms.load(Ordering::Relaxed) as f64),
+            ("vigil_average_poll_duration_ms", "Mean milliseconds per polling scan", self.average_poll_duration_ms()),
+        ];
+
+        gauges.iter().map(|(name, help, value)| format!("# HELP {} {}\n# TYPE {} gauge\n{} {}\n", name, help, name, name, value)).collect()
+    }
+}
+
+// Serialize paths lossily with forward slashes, so non-UTF-8 names get replacement characters instead of failing
+#[cfg(debug_assertions)]
+fn serialize_path<S: Serializer>(path: &Path, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&wire_path(path))
+}
+
+#[cfg(debug_assertions)]
+fn wire_path(path: &Path) -> String {
+    let path = path.to_string_lossy();
+    if std::path::MAIN_SEPARATOR == '\\' {
+        path.replace('\\', "/")
+    } else {
+        path.to_string()
+    }
+}
+
+#[cfg(debug_assertions)]
+fn serialize_optional_path<S: Serializer>(path: &Option<PathBuf>, serializer: S) -> Result<S::Ok, S::Error> {
+    match path {
+        Some(path) => serialize_path(path, serializer),
+        None => serializer.serialize_none(),
+    }
+}
+
+// Modification time in milliseconds and size of a watched file, plus its content hash with
+// `change_detection = "hash"`. Any of them differing counts as a change, which catches tools that
+// rewrite a file within the same timestamp, and all of them matching pairs up renames.
+#[cfg(debug_assertions)]
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct FileStamp {
+    mod_time: u64,
+    size: u64,
+    hash: Option<[u8; 32]>,
+}
+
+// State accumulated while walking the watched directories (polling fallback)
+#[cfg(debug_assertions)]
+#[derive(Default)]
+struct DirectoryScan {
+    latest_mod_time: u64,
+    // Watched files and their modification times and sizes
+    seen_files: HashMap<PathBuf, FileStamp>,
+    // Canonical paths of the directories walked so far with `follow_symlinks`
+    visited_dirs: HashSet<PathBuf>,
+    // Directories not entered because of `max_depth`
+    truncated_dirs: Vec<PathBuf>,
+    // Set once more than `max_watched_files` files were found, stopping the walk
+    limit_exceeded: bool,
+    // Listings from the previous scan, and the ones collected during this one
+    previous_listings: HashMap<PathBuf, DirectoryListing>,
+    listings: HashMap<PathBuf, DirectoryListing>,
+}
+
+#[cfg(debug_assertions)]
+impl DirectoryScan {
+    // Combine the results of walking another watch directory
+    fn merge(&mut self, other: DirectoryScan) {
+        self.latest_mod_time = self.latest_mod_time.max(other.latest_mod_time);
+        self.seen_files.extend(other.seen_files);
+        self.visited_dirs.extend(other.visited_dirs);
+        self.truncated_dirs.extend(other.truncated_dirs);
+        self.limit_exceeded |= other.limit_exceeded;
+        self.listings.extend(other.listings);
+    }
+
+    // Move the cached listings inside a watch directory out of the shared cache
+    fn take_listings_below(&mut self, root: &Path) -> HashMap<PathBuf, DirectoryListing> {
+        let dirs: Vec<PathBuf> = self.previous_listings.keys().filter(|dir| dir.starts_with(root)).cloned().collect();
+        dirs.into_iter().filter_map(|dir| self.previous_listings.remove(&dir).map(|listing| (dir, listing))).collect()
+    }
+}
+
+// A watched file as listed by /vigil/api/watched-files
+#[cfg(debug_assertions)]
+#[derive(Serialize)]
+struct WatchedFileInfo {
+    path: String,
+    // Milliseconds since the epoch when the file was last scanned
+    last_mtime: u64,
+    extension: String,
+}
+
+// Watched contents of a directory, reused while the directory itself is unchanged
+#[cfg(debug_assertions)]
+struct DirectoryListing {
+    mod_time: SystemTime,
+    listed_at: SystemTime,
+    // Subdirectories that aren't ignored, with whether each is a symlink
+    subdirs: Vec<(PathBuf, bool)>,
+    // Watched files that aren't ignored
+    files: Vec<PathBuf>,
+}
+
+// Programmatic configuration for applications that manage their own settings
+pub struct VigilBuilder {
+    environment: String,
+    config: VigilConfig,
+}
+
+impl VigilBuilder {
+    pub fn template_hot_reload(mut self, enabled: bool) -> Self {
+        self.config.template_hot_reload = enabled;
+        self
+    }
+
+    pub fn refresh_interval(mut self, milliseconds: u32) -> Self {
+        self.config.refresh_interval = milliseconds;
+        self
+    }
+
+    pub fn cooldown_period(mut self, milliseconds: u32) -> Self {
+        self.config.cooldown_period = milliseconds;
+        self
+    }
+
+    pub fn environment(mut self, environment: impl Into<String>) -> Self {
+        self.environment = environment.into();
+        self
+    }
+
+    // Watch these directories in addition to the defaults
+    pub fn extra_watch_dirs(mut self, dirs: Vec<PathBuf>) -> Self {
+        self.config.extra_watch_dirs.extend(dirs);
+        self
+    }
+
+    pub fn build(self) -> VigilSpark {
+        VigilSpark::from_parts(self.environment, self.config)
+    }
+}
+
+impl VigilSpark {
+    // "app/", "/app" and "/app/" all become "/app", and "/" or nothing an empty prefix
+    fn normalize_route_prefix(prefix: &str) -> String {
+        let prefix = prefix.trim().trim_matches('/');
+        if prefix.is_empty() {
+            String::new()
+        } else {
+            format!("/{}", prefix)
+        }
+    }
+
+    // Where pages load the reload script from
+    #[cfg(debug_assertions)]
+    fn dev_reload_script_url(config: &VigilConfig) -> String {
+        format!("{}{}", config.route_prefix, DEV_RELOAD_SCRIPT_PATH)
+    }
+
+    fn new() -> Self {
+        // Load environment setting from Catalyst.toml
+        let environment = Self::get_environment();
+
+        // Load config from manifest.toml and Catalyst.toml
+        let config = Self::load_config();
+        FOLLOW_CATALYST_TOML.store(true, Ordering::SeqCst);
+
+        Self::from_parts(environment, config)
+    }
+
+    // Create a builder that ignores Catalyst.toml and environment variables
+    pub fn builder() -> VigilBuilder {
+        VigilBuilder {
+            environment: "prod".to_string(),
+            config: VigilConfig::default(),
+        }
+    }
+
+    // Construct from a ready-made configuration, skipping Catalyst.toml, environment variables and manifest.toml
+    pub fn with_config(config: VigilConfig, environment: impl Into<String>) -> Self {
+        Self::from_parts(environment.into(), config)
+    }
+
+    // Forget the global instance, active configuration and scan state so the next spark created
+    // starts from scratch, e.g. between integration tests trying different configurations.
+    // Connected clients and a running watch loop are not stopped, so call it with no server running.
+    pub fn reset() {
+        if let Ok(mut global) = VIGIL_INSTANCE.write() {
+            *global = None;
+        }
+        if let Ok(mut active_config) = ACTIVE_CONFIG.write() {
+            *active_config = None;
+        }
+        FOLLOW_CATALYST_TOML.store(false, Ordering::SeqCst);
+
+        // Release builds never scan, so there is no scan state to forget
+        #[cfg(debug_assertions)]
+        {
+            if let Ok(mut listings) = DIRECTORY_LISTINGS.lock() {
+                *listings = None;
+            }
+            if let Ok(mut known_files) = KNOWN_FILES.lock() {
+                *known_files = None;
+            }
+            LAST_MOD_TIME.store(0, Ordering::SeqCst);
+            SENTINEL_MOD_TIME.store(0, Ordering::SeqCst);
+        }
+    }
+
+    // Environment of the global instance, "unknown" before a spark has been created
+    #[cfg(debug_assertions)]
+    fn global_environment() -> String {
+        VIGIL_INSTANCE.read().ok().and_then(|global| global.as_ref().map(|instance| instance.environment.clone())).unwrap_or_else(|| String::from("unknown"))
+    }
+
+    fn from_parts(environment: String, config: VigilConfig) -> Self {
+        // Report missing watch directories once instead of on every check
+        for dir in Self::watch_dirs(&config).iter().filter(|dir| !dir.exists()) {
+            cata_log!(Warning, format!("Vigil watch directory {} does not exist, watching for it to appear", dir.display()));
+        }
+
+        // Missing files are still checked on every scan in case they appear later
+        for file in config.watch_files.iter().filter(|file| !file.exists()) {
+            cata_log!(Warning, format!("Vigil watch file {} does not exist, watching for it to appear", file.display()));
+        }
+
+        cata_log!(Info, format!("Vigil: Excluding directories {:?}", config.exclude_dirs));
+
+        let instance = Self { environment, config };
+        STARTED_AT.get_or_init(std::time::Instant::now);
+
+        // Store the instance for global access
+        if let Ok(mut global) = VIGIL_INSTANCE.write() {
+            if global.is_none() {
+                *global = Some(instance.clone());
+            }
+        }
+        if let Ok(mut active_config) = ACTIVE_CONFIG.write() {
+            if active_config.is_none() {
+                *active_config = Some(instance.config.clone());
+            }
+        }
+
+        instance
+    }
+
+    // Parse manifest.toml and Catalyst.toml for configuration
+    fn load_config() -> VigilConfig {
+        Self::config_from_toml(Self::parse_catalyst_toml())
+    }
+
+    // Build the configuration from a parsed Catalyst.toml, if any, and the other sources
+    fn config_from_toml(toml_config: Option<toml::Value>) -> VigilConfig {
+        // Report typos and suspicious values instead of silently using defaults
+        if let Some(toml) = &toml_config {
+            for problem in Self::validate_vigil_config(toml) {
+                cata_log!(Warning, format!("Invalid [spark.vigil] config: {}", problem));
+            }
+        }
+
+        // Default configuration values from manifest.toml
+        let defaults = VigilConfig::default();
+
+        // Build config with cascading priority: Catalyst.toml -> env -> manifest.toml -> defaults
+        let template_hot_reload = Self::get_config_bool(&toml_config, "template_hot_reload", "VIGIL_TEMPLATE_HOT_RELOAD", defaults.template_hot_reload);
+
+        let body_injection = Self::get_config_bool(&toml_config, "body_injection", "VIGIL_BODY_INJECTION", defaults.body_injection);
+
+        let refresh_interval = Self::get_config_integer(&toml_config, "refresh_interval", "VIGIL_REFRESH_INTERVAL", defaults.refresh_interval as i64) as u32;
+
+        let debounce_ms = Self::get_config_integer(&toml_config, "debounce_ms", "VIGIL_DEBOUNCE_MS", defaults.debounce_ms as i64) as u32;
+
+        let cooldown_period = Self::get_config_integer(&toml_config, "cooldown_period", "VIGIL_COOLDOWN_PERIOD", defaults.cooldown_period as i64) as u32;
+        let reload_delay = Self::get_config_integer(&toml_config, "reload_delay", "VIGIL_RELOAD_DELAY", defaults.reload_delay as i64).max(0) as u32;
+
+        let sse_fallback_retries = Self::get_config_integer(&toml_config, "sse_fallback_retries", "VIGIL_SSE_FALLBACK_RETRIES", defaults.sse_fallback_retries as i64) as u32;
+        let long_poll_timeout = Self::get_config_integer(&toml_config, "long_poll_timeout", "VIGIL_LONG_POLL_TIMEOUT", defaults.long_poll_timeout as i64).max(0) as u32;
+
+        // `keepalive_interval` is an alias that wins over `ping_interval` when both are set
+        let ping_interval = Self::get_config_integer(&toml_config, "ping_interval", "VIGIL_PING_INTERVAL", defaults.ping_interval as i64);
+        let ping_interval = Self::get_config_integer(&toml_config, "keepalive_interval", "VIGIL_KEEPALIVE_INTERVAL", ping_interval).max(0) as u32;
+
+        let max_ws_connections = Self::get_config_integer(&toml_config, "max_ws_connections", "VIGIL_MAX_WS_CONNECTIONS", defaults.max_ws_connections as i64).max(0) as u32;
+        let max_errors = Self::get_config_integer(&toml_config, "max_errors", "VIGIL_MAX_ERRORS", defaults.max_errors as i64).max(0) as u32;
+
+        let watch_rust_sources = Self::get_config_bool(&toml_config, "watch_rust_sources", "VIGIL_WATCH_RUST_SOURCES", defaults.watch_rust_sources);
+
+        let hash_check = Self::get_config_bool(&toml_config, "hash_check", "VIGIL_HASH_CHECK", defaults.hash_check);
+
+        let change_detection = Self::parse_change_detection(&Self::get_config_string(&toml_config, "change_detection", "VIGIL_CHANGE_DETECTION", defaults.change_detection.as_str()));
+
+        let respect_gitignore = Self::get_config_bool(&toml_config, "respect_gitignore", "VIGIL_RESPECT_GITIGNORE", defaults.respect_gitignore);
+
+        let follow_symlinks = Self::get_config_bool(&toml_config, "follow_symlinks", "VIGIL_FOLLOW_SYMLINKS", defaults.follow_symlinks);
+
+        let watch_hidden = Self::get_config_bool(&toml_config, "watch_hidden", "VIGIL_WATCH_HIDDEN", defaults.watch_hidden);
+
+        let parallel_scan = Self::get_config_bool(&toml_config, "parallel_scan", "VIGIL_PARALLEL_SCAN", defaults.parallel_scan);
+
+        let dry_run = Self::get_config_bool(&toml_config, "dry_run", "VIGIL_DRY_RUN", defaults.dry_run);
+
+        let partial_reload = Self::get_config_bool(&toml_config, "partial_reload", "VIGIL_PARTIAL_RELOAD", defaults.partial_reload);
+        let css_hot_swap = Self::get_config_bool(&toml_config, "css_hot_swap", "VIGIL_CSS_HOT_SWAP", defaults.css_hot_swap);
+
+        // Negative values mean unlimited. `watch_depth` is an alias that wins over `max_depth` when both are set.
+        let max_depth = Self::get_config_integer(&toml_config, "max_depth", "VIGIL_MAX_DEPTH", defaults.max_depth.map_or(-1, i64::from));
+        let max_depth = u32::try_from(Self::get_config_integer(&toml_config, "watch_depth", "VIGIL_WATCH_DEPTH", max_depth)).ok();
+
+        let max_watched_files = Self::get_config_integer(&toml_config, "max_watched_files", "VIGIL_MAX_WATCHED_FILES", defaults.max_watched_files as i64) as u32;
+
+        let max_watch_file_size_kb = Self::get_config_integer(&toml_config, "max_watch_file_size_kb", "VIGIL_MAX_WATCH_FILE_SIZE_KB", defaults.max_watch_file_size_kb as i64).max(0) as u64;
+
+        // Directories watched even when .gitignore excludes them, e.g. a generated dist/
+        let default_gitignore_overrides = defaults.gitignore_overrides.iter().map(|dir| dir.to_string_lossy().to_string()).collect();
+        let gitignore_overrides: Vec<PathBuf> = Self::get_config_string_list(&toml_config, "gitignore_overrides", "VIGIL_GITIGNORE_OVERRIDES", default_gitignore_overrides).into_iter().map(PathBuf::from).collect();
+
+        // Configured directories replace the defaults entirely
+        let mut watch_dirs = Self::get_config_watch_dirs(&toml_config, defaults.watch_dirs.clone());
+        for dir in &mut watch_dirs {
+            dir.path = Self::resolve_watch_dir(std::mem::take(&mut dir.path));
+        }
+
+        // Extra directories are watched in addition to `watch_dirs`
+        let mut extra_watch_dirs: Vec<PathBuf> = Self::get_config_path_list(&toml_config, "extra_watch_dirs", "VIGIL_EXTRA_WATCH_DIRS", defaults.extra_watch_dirs.clone()).into_iter().map(Self::resolve_watch_dir).collect();
+        Self::dedupe_watch_dirs(&mut watch_dirs, &mut extra_watch_dirs);
+
+        // Individual files are stat'ed on every scan alongside the directory walk
+        let default_watch_files = defaults.watch_files.iter().map(|file| file.to_string_lossy().to_string()).collect();
+        let watch_files: Vec<PathBuf> = Self::get_config_string_list(&toml_config, "watch_files", "VIGIL_WATCH_FILES", default_watch_files).into_iter().map(PathBuf::from).collect();
+
+        let locales_dir = PathBuf::from(Self::get_config_string(&toml_config, "locales_dir", "VIGIL_LOCALES_DIR", &defaults.locales_dir.to_string_lossy()));
+
+        // An empty sentinel file means scanning the watch directories as usual
+        let default_sentinel_file = defaults.sentinel_file.as_ref().map(|file| file.to_string_lossy().to_string()).unwrap_or_default();
+        let sentinel_file = Some(Self::get_config_string(&toml_config, "sentinel_file", "VIGIL_SENTINEL_FILE", &default_sentinel_file)).filter(|file| !file.is_empty()).map(PathBuf::from);
+
+        // Configured exclusions extend the built-in list rather than replacing it
+        let exclude_dirs = Self::merge_exclude_dirs(defaults.exclude_dirs.clone(), Self::get_config_string_list(&toml_config, "exclude_dirs", "VIGIL_EXCLUDE_DIRS", Vec::new()));
+
+        // Configured extensions replace the built-in list
+        let extensions = Self::normalize_extensions(Self::get_config_string_list(&toml_config, "extensions", "VIGIL_EXTENSIONS", defaults.extensions.clone()));
+
+        // Assets are watched in addition to `extensions` and refreshed without a full reload
+        let asset_extensions = Self::normalize_extensions(Self::get_config_string_list(&toml_config, "asset_extensions", "VIGIL_ASSET_EXTENSIONS", defaults.asset_extensions.clone()));
+
+        // Compile ignore patterns once so they aren't re-parsed for every file
+        let default_ignore = defaults.ignore.iter().map(|pattern| pattern.as_str().to_string()).collect();
+        let ignore = Self::compile_patterns(&Self::get_config_string_list(&toml_config, "ignore", "VIGIL_IGNORE", default_ignore));
+
+        let default_ignore_paths = defaults.ignore_paths.iter().map(|pattern| pattern.as_str().to_string()).collect();
+        let ignore_paths = Self::compile_patterns(&Self::get_config_string_list(&toml_config, "ignore_paths", "VIGIL_IGNORE_PATHS", default_ignore_paths));
+
+        // Include patterns replace the extension allowlist when set, exclude patterns always win
+        let default_include = defaults.watch_include_patterns.iter().map(|pattern| pattern.as_str().to_string()).collect();
+        let watch_include_patterns = Self::compile_patterns(&Self::get_config_string_list(&toml_config, "watch_include_patterns", "VIGIL_WATCH_INCLUDE_PATTERNS", default_include));
+
+        let default_exclude = defaults.watch_exclude_patterns.iter().map(|pattern| pattern.as_str().to_string()).collect();
+        let watch_exclude_patterns = Self::compile_patterns(&Self::get_config_string_list(&toml_config, "watch_exclude_patterns", "VIGIL_WATCH_EXCLUDE_PATTERNS", default_exclude));
+
+        let allowed_origins = Self::get_config_string_list(&toml_config, "allowed_origins", "VIGIL_ALLOWED_ORIGINS", defaults.allowed_origins.clone());
+        let cors_origins = Self::get_config_string_list(&toml_config, "cors_origins", "VIGIL_CORS_ORIGINS", defaults.cors_origins.clone());
+
+        // Where browsers reach Vigil when a reverse proxy sits in front of the server
+        let public_ws_url = Self::get_config_string(&toml_config, "public_ws_url", "VIGIL_PUBLIC_WS_URL", &defaults.public_ws_url);
+        let route_prefix = Self::normalize_route_prefix(&Self::get_config_string(&toml_config, "route_prefix", "VIGIL_ROUTE_PREFIX", &defaults.route_prefix));
+
+        // Development routes only answer this machine unless opened up
+        let allow_remote = Self::get_config_bool(&toml_config, "allow_remote", "VIGIL_ALLOW_REMOTE", defaults.allow_remote);
+        let allowed_ips = Self::get_config_string_list(&toml_config, "allowed_ips", "VIGIL_ALLOWED_IPS", defaults.allowed_ips.clone());
+        for entry in allowed_ips.iter().filter(|entry| parse_ip_range(entry).is_none()) {
+            cata_log!(Warning, format!("Ignoring invalid allowed_ips entry {:?}, expected an address or CIDR range", entry));
+        }
+        let trust_proxy_headers = Self::get_config_bool(&toml_config, "trust_proxy_headers", "VIGIL_TRUST_PROXY_HEADERS", defaults.trust_proxy_headers);
+
+        // An empty value leaves the routes unprotected, same as not setting it
+        let vigil_api_username = Some(Self::get_config_string(&toml_config, "vigil_api_username", "VIGIL_API_USERNAME", defaults.vigil_api_username.as_deref().unwrap_or(""))).filter(|username| !username.is_empty());
+        let vigil_api_password = Some(Self::get_config_string(&toml_config, "vigil_api_password", "VIGIL_API_PASSWORD", defaults.vigil_api_password.as_ref().map(ApiPassword::expose).unwrap_or(""))).filter(|password| !password.is_empty()).map(ApiPassword);
+        let dev_token = Some(Self::get_config_string(&toml_config, "dev_token", "VIGIL_DEV_TOKEN", defaults.dev_token.as_ref().map(ApiPassword::expose).unwrap_or(""))).filter(|token| !token.is_empty()).map(ApiPassword);
+
+        let config = VigilConfig {
+            template_hot_reload,
+            body_injection,
+            refresh_interval,
+            debounce_ms,
+            cooldown_period,
+            reload_delay,
+            sse_fallback_retries,
+            long_poll_timeout,
+            ping_interval,
+            max_ws_connections,
+            max_errors,
+            watch_rust_sources,
+            hash_check,
+            change_detection,
+            respect_gitignore,
+            follow_symlinks,
+            watch_hidden,
+            parallel_scan,
+            dry_run,
+            partial_reload,
+            css_hot_swap,
+            max_depth,
+            max_watched_files,
+            max_watch_file_size_kb,
+            gitignore_overrides,
+            watch_dirs,
+            extra_watch_dirs,
+            watch_files,
+            locales_dir,
+            sentinel_file,
+            exclude_dirs,
+            extensions,
+            asset_extensions,
+            ignore,
+            ignore_paths,
+            watch_include_patterns,
+            watch_exclude_patterns,
+            allowed_origins,
+            cors_origins,
+            public_ws_url,
+            route_prefix,
+            allow_remote,
+            allowed_ips,
+            trust_proxy_headers,
+            vigil_api_username,
+            vigil_api_password,
+            dev_token,
+        };
+
+        cata_log!(Info, format!("Vigil config loaded:\n{}", config));
+
+        config
+    }
+
+    // Canonicalize absolute paths and paths containing `..`, such as a sibling checkout, so their files
+    // are reported by their real location. Paths inside the project are kept relative, and paths that
+    // don't exist yet are kept as configured.
+    fn resolve_watch_dir(path: PathBuf) -> PathBuf {
+        let leaves_project = path.is_absolute() || path.components().any(|component| component == Component::ParentDir);
+        if !leaves_project {
+            return path;
+        }
+
+        path.canonicalize().unwrap_or(path)
+    }
+
+    // Drop directories listed more than once, e.g. both relative and absolute, which would
+    // otherwise report every change in them twice
+    fn dedupe_watch_dirs(watch_dirs: &mut Vec<WatchDirConfig>, extra_watch_dirs: &mut Vec<PathBuf>) {
+        let mut seen_dirs = HashSet::new();
+        let mut is_new = |dir: &Path| {
+            let is_new = seen_dirs.insert(dir.canonicalize().unwrap_or_else(|_| dir.to_path_buf()));
+            if !is_new {
+                cata_log!(Warning, format!("Vigil watch directory {} is listed more than once, ignoring the duplicate", dir.display()));
+            }
+            is_new
+        };
+
+        watch_dirs.retain(|dir| is_new(&dir.path));
+        extra_watch_dirs.retain(|dir| is_new(dir));
+    }
+
+    fn parse_change_detection(value: &str) -> ChangeDetection {
+        match value.trim().to_lowercase().as_str() {
+            "hash" => ChangeDetection::Hash,
+            "mtime" => ChangeDetection::Mtime,
+            other => {
+                cata_log!(Warning, format!("Unknown Vigil change_detection {:?}, expected \"mtime\" or \"hash\", using mtime", other));
+                ChangeDetection::Mtime
+            }
+        }
+    }
+
+    // Add configured directory names to the built-in exclusions, skipping duplicates
+    fn merge_exclude_dirs(mut exclude_dirs: Vec<String>, extra: Vec<String>) -> Vec<String> {
+        for dir in extra.into_iter().map(|dir| dir.trim().trim_matches('/').to_string()).filter(|dir| !dir.is_empty()) {
+            if !exclude_dirs.contains(&dir) {
+                exclude_dirs.push(dir);
+            }
+        }
+
+        exclude_dirs
+    }
+
+    // Collect unknown keys, wrongly typed values and out-of-range values in [spark.vigil]
+    fn validate_vigil_config(toml: &toml::Value) -> Vec<String> {
+        let Some(section) = toml.get("spark").and_then(|s| s.get("vigil")) else {
+            return Vec::new();
+        };
+        let Some(table) = section.as_table() else {
+            return vec![String::from("[spark.vigil] must be a table")];
+        };
+
+        let mut problems = Vec::new();
+        for (key, value) in table {
+            let key = key.as_str();
+            let type_error = if BOOL_CONFIG_KEYS.contains(&key) {
+                (!value.is_bool()).then_some("a boolean")
+            } else if INTEGER_CONFIG_KEYS.contains(&key) {
+                (!value.is_integer()).then_some("an integer")
+            } else if key == "watch_dirs" {
+                let valid_entry = |item: &toml::Value| item.is_str() || item.get("path").map(|path| path.is_str()).unwrap_or(false);
+                (!value.as_array().map(|items| items.iter().all(valid_entry)).unwrap_or(false)).then_some("an array of paths or { path, extensions } tables")
+            } else if STRING_LIST_CONFIG_KEYS.contains(&key) {
+                (!value.as_array().map(|items| items.iter().all(|item| item.is_str())).unwrap_or(false)).then_some("an array of strings")
+            } else if STRING_CONFIG_KEYS.contains(&key) {
+                (!value.is_str()).then_some("a string")
+            } else {
+                problems.push(format!("unknown key `{}`", key));
+                continue;
+            };
+
+            if let Some(expected) = type_error {
+                problems.push(format!("`{}` should be {}, got `{}`", key, expected, value));
+            }
+        }
+
+        let integer = |key: &str| table.get(key).and_then(|value| value.as_integer());
+        for key in INTEGER_CONFIG_KEYS {
+            if let Some(value) = integer(key).filter(|value| *value < 0) {
+                problems.push(format!("`{}` must not be negative, got {}", key, value));
+            }
+        }
+
+        if let Some(refresh_interval) = integer("refresh_interval").filter(|value| *value < MIN_REFRESH_INTERVAL_MS) {
+            problems.push(format!("`refresh_interval` below {}ms will use a lot of CPU, got {}", MIN_REFRESH_INTERVAL_MS, refresh_interval));
+        }
+
+        if let (Some(cooldown_period), Some(refresh_interval)) = (integer("cooldown_period"), integer("refresh_interval")) {
+            if cooldown_period < refresh_interval {
+                problems.push(format!("`cooldown_period` ({}) is shorter than `refresh_interval` ({})", cooldown_period, refresh_interval));
+            }
+        }
+
+        problems
+    }
+
+    // Values that would make the watcher unusable, reported as an initialization error
+    fn critical_config_error(config: &VigilConfig) -> Option<String> {
+        if config.refresh_interval == 0 {
+            return Some(String::from("refresh_interval must be greater than 0"));
+        }
+
+        [("refresh_interval", config.refresh_interval), ("debounce_ms", config.debounce_ms), ("cooldown_period", config.cooldown_period)]
+            .iter()
+            .find(|(_, value)| *value > MAX_INTERVAL_MS)
+            .map(|(key, value)| format!("{} must be at most {}ms, got {}", key, MAX_INTERVAL_MS, value))
+    }
+
+    // Lowercase extensions and strip leading dots so ".HBS" and "hbs" are equivalent
+    fn normalize_extensions(extensions: Vec<String>) -> Vec<String> {
+        extensions.iter().map(|ext| ext.trim().trim_start_matches('.').to_lowercase()).filter(|ext| !ext.is_empty()).collect()
+    }
+
+    // Compile glob patterns, skipping (and reporting) invalid ones
+    fn compile_patterns(patterns: &[String]) -> Vec<glob::Pattern> {
+        patterns
+            .iter()
+            .filter_map(|pattern| match glob::Pattern::new(pattern) {
+                Ok(pattern) => Some(pattern),
+                Err(e) => {
+                    cata_log!(Warning, format!("Invalid Vigil glob pattern {:?}: {}", pattern, e));
+                    None
+                }
+            })
+            .collect()
+    }
+
+    // Parse Catalyst.toml file
+    fn parse_catalyst_toml() -> Option<toml::Value> {
+        use std::fs;
+
+        let config_path = "Catalyst.toml";
+        let config_str = fs::read_to_string(config_path).unwrap_or_else(|_| {
+            cata_log!(Warning, "Could not find Catalyst.toml, using default configuration");
+            String::new()
+        });
+
+        if !config_str.is_empty() {
+            match toml::from_str::<toml::Value>(&config_str) {
+                Ok(config) => Some(config),
+                Err(e) => {
+                    cata_log!(Error, format!("Failed to parse Catalyst.toml: {}", e));
+                    None
+                }
+            }
+        } else {
+            None
+        }
+    }
+
+    // Get boolean value from manifest.toml config.defaults section
+    fn get_manifest_bool(key: &str, default: bool) -> bool {
+        if let Ok(manifest) = toml::from_str::<toml::Value>(MANIFEST_TOML) {
+            // Check in config.defaults section
+            if let Some(config) = manifest.get("config") {
+                if let Some(defaults) = config.get("defaults") {
+                    if let Some(value) = defaults.get(key) {
+                        if let Some(bool_value) = value.as_bool() {
+                            return bool_value;
+                        }
+                    }
+                }
+            }
+
+            // Also check at root level for backward compatibility
+            if let Some(value) = manifest.get(key) {
+                if let Some(bool_value) = value.as_bool() {
+                    return bool_value;
+                }
+            }
+        }
+        default
+    }
+
+    // Get integer value from manifest.toml config.defaults section
+    fn get_manifest_integer(key: &str, default: i64) -> i64 {
+        if let Ok(manifest) = toml::from_str::<toml::Value>(MANIFEST_TOML) {
+            // Check in config.defaults section
+            if let Some(config) = manifest.get("config") {
+                if let Some(defaults) = config.get("defaults") {
+                    if let Some(value) = defaults.get(key) {
+                        if let Some(int_value) = value.as_integer() {
+                            return int_value;
+                        }
+                    }
+                }
+            }
+
+            // Also check at root level for backward compatibility
+            if let Some(value) = manifest.get(key) {
+                if let Some(int_value) = value.as_integer() {
+                    return int_value;
+                }
+            }
+        }
+        default
+    }
+
+    // Get string list value from manifest.toml config.defaults section
+    fn get_manifest_string_list(key: &str, default: Vec<String>) -> Vec<String> {
+        if let Ok(manifest) = toml::from_str::<toml::Value>(MANIFEST_TOML) {
+            // Check in config.defaults section
+            if let Some(config) = manifest.get("config") {
+                if let Some(defaults) = config.get("defaults") {
+                    if let Some(value) = defaults.get(key) {
+                        if let Some(list) = Self::as_string_list(value) {
+                            return list;
+                        }
+                    }
+                }
+            }
+
+            // Also check at root level for backward compatibility
+            if let Some(value) = manifest.get(key) {
+                if let Some(list) = Self::as_string_list(value) {
+                    return list;
+                }
+            }
+        }
+        default
+    }
+
+    // Get string value from manifest.toml config.defaults section
+    fn get_manifest_string(key: &str, default: &str) -> String {
+        if let Ok(manifest) = toml::from_str::<toml::Value>(MANIFEST_TOML) {
+            // Check in config.defaults section
+            if let Some(config) = manifest.get("config") {
+                if let Some(defaults) = config.get("defaults") {
+                    if let Some(value) = defaults.get(key) {
+                        if let Some(str_value) = value.as_str() {
+                            return str_value.to_string();
+                        }
+                    }
+                }
+            }
+
+            // Also check at root level for backward compatibility
+            if let Some(value) = manifest.get(key) {
+                if let Some(str_value) = value.as_str() {
+                    return str_value.to_string();
+                }
+            }
+        }
+        default.to_string()
+    }
+
+    // Convert a TOML array into a list of strings, ignoring non-string entries
+    fn as_string_list(value: &toml::Value) -> Option<Vec<String>> {
+        value.as_array().map(|items| items.iter().filter_map(|item| item.as_str()).map(|item| item.to_string()).collect())
+    }
+
+    // Helper to get a boolean config value with fallback to environment and default
+    fn get_config_bool(toml_config: &Option<toml::Value>, key: &str, env_key: &str, default: bool) -> bool {
+        use std::env;
+
+        toml_config
+            .as_ref()
+            .and_then(|c| c.get("spark"))
+            .and_then(|s| s.get("vigil"))
+            .and_then(|p| p.get(key))
+            .and_then(|v| v.as_bool())
+            .unwrap_or_else(|| env::var(env_key).unwrap_or_else(|_| default.to_string()).parse().unwrap_or(default))
+    }
+
+    // Helper to get an integer config value with fallback to environment and default
+    fn get_config_integer(toml_config: &Option<toml::Value>, key: &str, env_key: &str, default: i64) -> i64 {
+        use std::env;
+
+        toml_config
+            .as_ref()
+            .and_then(|c| c.get("spark"))
+            .and_then(|s| s.get("vigil"))
+            .and_then(|p| p.get(key))
+            .and_then(|v| v.as_integer())
+            .unwrap_or_else(|| env::var(env_key).unwrap_or_else(|_| default.to_string()).parse().unwrap_or(default))
+    }
+
+    // Helper to get a string config value with fallback to environment and default
+    fn get_config_string(toml_config: &Option<toml::Value>, key: &str, env_key: &str, default: &str) -> String {
+        use std::env;
+
+        toml_config
+            .as_ref()
+            .and_then(|c| c.get("spark"))
+            .and_then(|s| s.get("vigil"))
+            .and_then(|p| p.get(key))
+            .and_then(|v| v.as_str())
+            .map(|v| v.to_string())
+            .unwrap_or_else(|| env::var(env_key).unwrap_or_else(|_| default.to_string()))
+    }
+
+    // Helper to get a string list config value with fallback to a comma-separated environment variable and default
+    fn get_config_string_list(toml_config: &Option<toml::Value>, key: &str, env_key: &str, default: Vec<String>) -> Vec<String> {
+        use std::env;
+
+        toml_config
+            .as_ref()
+            .and_then(|c| c.get("spark"))
+            .and_then(|s| s.get("vigil"))
+            .and_then(|p| p.get(key))
+            .and_then(Self::as_string_list)
+            .unwrap_or_else(|| match env::var(env_key) {
+                Ok(value) => value.split(',').map(|item| item.trim()).filter(|item| !item.is_empty()).map(|item| item.to_string()).collect(),
+                Err(_) => default,
+            })
+    }
+
+    // Helper to get a path list config value with fallback to an environment variable split like PATH (or on commas) and default
+    fn get_config_path_list(toml_config: &Option<toml::Value>, key: &str, env_key: &str, default: Vec<PathBuf>) -> Vec<PathBuf> {
+        use std::env;
+
+        toml_config
+            .as_ref()
+            .and_then(|c| c.get("spark"))
+            .and_then(|s| s.get("vigil"))
+            .and_then(|p| p.get(key))
+            .and_then(Self::as_string_list)
+            .map(|items| items.into_iter().map(PathBuf::from).collect())
+            .unwrap_or_else(|| match env::var(env_key) {
+                Ok(value) => Self::split_env_paths(&value),
+                Err(_) => default,
+            })
+    }
+
+    // Split an environment variable on commas and the platform path separator (`:`, or `;` on Windows)
+    fn split_env_paths(value: &str) -> Vec<PathBuf> {
+        value.split(',').map(|item| item.trim()).flat_map(std::env::split_paths).filter(|path| !path.as_os_str().is_empty()).collect()
+    }
+
+    // Helper to get the watch directories from Catalyst.toml or the default, plus VIGIL_WATCH_DIRS
+    // VIGIL_WATCH_DIRS (comma- or path-separator-separated, i.e. `:` or `;` on Windows) is appended to the
+    // Catalyst.toml list, or to the defaults, so containers and CI can add roots without editing the file.
+    // VIGIL_WATCH_MODE=replace uses only its directories; a leading `+` is accepted for older setups.
+    fn get_config_watch_dirs(toml_config: &Option<toml::Value>, default: Vec<WatchDirConfig>) -> Vec<WatchDirConfig> {
+        use std::env;
+
+        let configured = toml_config.as_ref().and_then(|c| c.get("spark")).and_then(|s| s.get("vigil")).and_then(|p| p.get("watch_dirs")).and_then(Self::as_watch_dir_list);
+        let Ok(value) = env::var("VIGIL_WATCH_DIRS") else {
+            return configured.unwrap_or(default);
+        };
+
+        let value = value.trim();
+        let value = value.strip_prefix('+').unwrap_or(value);
+        // Directories from the environment usually come from container or CI setups, so typos are
+        // reported and skipped instead of waiting for the directory to appear
+        let env_dirs: Vec<WatchDirConfig> = Self::split_env_paths(value)
+            .into_iter()
+            .filter(|path| {
+                let is_dir = path.is_dir();
+                if !is_dir {
+                    cata_log!(Warning, format!("Ignoring {} from VIGIL_WATCH_DIRS, it is not a directory", path.display()));
+                }
+                is_dir
+            })
+            .map(WatchDirConfig::new)
+            .collect();
+
+        let mode = env::var("VIGIL_WATCH_MODE").unwrap_or_default().trim().to_lowercase();
+        if !matches!(mode.as_str(), "" | "append" | "replace") {
+            cata_log!(Warning, format!("Unknown VIGIL_WATCH_MODE {:?}, expected append or replace", mode));
+        }
+        if mode == "replace" {
+            return env_dirs;
+        }
+
+        let mut dirs = configured.unwrap_or(default);
+        for dir in env_dirs {
+            if !dirs.iter().any(|existing| existing.path == dir.path) {
+                dirs.push(dir);
+            }
+        }
+        dirs
+    }
+
+    // Convert a TOML array of paths or { path, extensions } tables into watch directories
+    fn as_watch_dir_list(value: &toml::Value) -> Option<Vec<WatchDirConfig>> {
+        value.as_array().map(|items| {
+            items
+                .iter()
+                .filter_map(|item| match item {
+                    toml::Value::String(path) => Some(WatchDirConfig::new(path)),
+                    toml::Value::Table(table) => table.get("path").and_then(|path| path.as_str()).map(|path| WatchDirConfig {
+                        path: PathBuf::from(path),
+                        extensions: table.get("extensions").and_then(Self::as_string_list).map(Self::normalize_extensions),
+                    }),
+                    _ => None,
+                })
+                .collect()
+        })
+    }
+
+    // Helper to get the current environment from Catalyst.toml
+    fn get_environment() -> String {
+        let config_path = "Catalyst.toml";
+        let config_str = fs::read_to_string(config_path).unwrap_or_default();
+
+        if !config_str.is_empty() {
+            if let Ok(toml) = toml::from_str::<toml::Value>(&config_str) {
+                if let Some(settings) = toml.get("settings") {
+                    if let Some(env) = settings.get("environment") {
+                        if let Some(env_str) = env.as_str() {
+                            return env_str.to_string();
+                        }
+                    }
+                }
+            }
+        }
+
+        // Default to production if not specified
+        "prod".to_string()
+    }
+
+    // Get the active configuration
+    #[cfg(debug_assertions)]
+    fn current_config() -> VigilConfig {
+        ACTIVE_CONFIG.read().ok().and_then(|config| config.clone()).unwrap_or_else(Self::load_config)
+    }
+
+    // Interval between WebSocket ping frames. A zero interval would make tokio panic, so pings
+    // go out at least a second apart.
+    #[cfg(debug_assertions)]
+    fn keepalive_period(config: &VigilConfig) -> std::time::Duration {
+        std::time::Duration::from_millis(config.ping_interval.max(1000) as u64)
+    }
+
+    // List the settings that differ between two configurations as (key, old, new)
+    #[cfg(debug_assertions)]
+    fn config_changes(old: &VigilConfig, new: &VigilConfig) -> Vec<(String, serde_json::Value, serde_json::Value)> {
+        let (Ok(serde_json::Value::Object(old)), Ok(serde_json::Value::Object(new))) = (serde_json::to_value(old), serde_json::to_value(new)) else {
+            return Vec::new();
+        };
+
+        new.into_iter().filter_map(|(key, new_value)| old.get(&key).filter(|old_value| **old_value != new_value).map(|old_value| (key, old_value.clone(), new_value))).collect()
+    }
+
+    // Get the directories to watch for the given configuration
+    fn watch_dirs(config: &VigilConfig) -> Vec<PathBuf> {
+        let mut dirs: Vec<PathBuf> = config.watch_dirs.iter().map(|dir| dir.path.clone()).collect();
+
+        for dir in &config.extra_watch_dirs {
+            if !dirs.contains(dir) {
+                dirs.push(dir.clone());
+            }
+        }
+
+        if config.watch_rust_sources {
+            dirs.push(PathBuf::from(RUST_SOURCE_DIR));
+        }
+
+        // Most projects have no translations, so a missing locales directory isn't worth a warning
+        if config.locales_dir.is_dir() && !dirs.contains(&config.locales_dir) {
+            dirs.push(config.locales_dir.clone());
+        }
+
+        dirs
+    }
+
+    // Check whether a path has one of the watched extensions, or matches an include pattern if any are set
+    #[cfg(debug_assertions)]
+    fn is_watched_file(path: &Path, config: &VigilConfig) -> bool {
+        if Self::is_explicit_watch_file(path, config) || Self::is_translation_file(path, config) {
+            return true;
+        }
+
+        if config.watch_rust_sources && Self::is_rust_source(path) {
+            return true;
+        }
+
+        if !config.watch_include_patterns.is_empty() {
+            let relative_path = Self::relative_path(path);
+            return config.watch_include_patterns.iter().any(|pattern| pattern.matches_path(&relative_path));
+        }
+
+        let Some(ext) = path.extension().map(|ext| ext.to_string_lossy().to_lowercase()) else {
+            return false;
+        };
+
+        match Self::watch_dir_extensions(path, config) {
+            Some(extensions) => extensions.contains(&ext),
+            None => config.extensions.contains(&ext) || config.asset_extensions.contains(&ext),
+        }
+    }
+
+    // Check whether a path is a translation catalog inside the locales directory
+    #[cfg(debug_assertions)]
+    fn is_translation_file(path: &Path, config: &VigilConfig) -> bool {
+        let in_locales_dir = Self::relative_path(path).starts_with(Self::relative_path(&config.locales_dir));
+        in_locales_dir && path.extension().map(|ext| TRANSLATION_EXTENSIONS.contains(&ext.to_string_lossy().to_lowercase().as_str())).unwrap_or(false)
+    }
+
+    // Check whether a path is one of the individually configured `watch_files`
+    #[cfg(debug_assertions)]
+    fn is_explicit_watch_file(path: &Path, config: &VigilConfig) -> bool {
+        let relative_path = Self::relative_path(path);
+        config.watch_files.iter().any(|file| Self::relative_path(file) == relative_path)
+    }
+
+    // Extensions configured for the innermost watch directory containing a path, if it has its own list
+    #[cfg(debug_assertions)]
+    fn watch_dir_extensions<'a>(path: &Path, config: &'a VigilConfig) -> Option<&'a Vec<String>> {
+        let relative_path = Self::relative_path(path);
+
+        config
+            .watch_dirs
+            .iter()
+            .filter(|dir| relative_path.starts_with(Self::relative_path(&dir.path)))
+            .max_by_key(|dir| dir.path.components().count())
+            .and_then(|dir| dir.extensions.as_ref())
+    }
+
+    // Check whether an extension belongs to the asset category of the active configuration
+    #[cfg(debug_assertions)]
+    fn is_asset_extension(ext: &str) -> bool {
+        match ACTIVE_CONFIG.read().ok().as_ref().and_then(|config| config.as_ref()) {
+            Some(config) => config.asset_extensions.iter().any(|asset_ext| asset_ext == ext),
+            None => ASSET_EXTENSIONS.contains(&ext),
+        }
+    }
+
+    // Check whether a path matches one of the configured ignore or exclude patterns
+    #[cfg(debug_assertions)]
+    fn is_ignored(path: &Path, config: &VigilConfig) -> bool {
+        // Explicitly configured files are always watched
+        if Self::is_explicit_watch_file(path, config) {
+            return false;
+        }
+
+        if Self::is_in_excluded_dir(path, config) || Self::is_hidden_or_temp_file(path, config) {
+            return true;
+        }
+
+        if config.respect_gitignore && Self::is_gitignored(path, config) {
+            return true;
+        }
+
+        if config.ignore.is_empty() && config.watch_exclude_patterns.is_empty() && config.ignore_paths.is_empty() {
+            return false;
+        }
+
+        let relative_path = Self::relative_path(path);
+
+        // `ignore_paths` also match through the parent directories, so "templates/generated" skips
+        // the directory while walking and anything below it reported by native events
+        let matched = config
+            .ignore
+            .iter()
+            .chain(config.watch_exclude_patterns.iter())
+            .find(|pattern| pattern.matches_path(&relative_path))
+            .or_else(|| config.ignore_paths.iter().find(|pattern| relative_path.ancestors().any(|ancestor| pattern.matches_path(ancestor))));
+
+        match matched {
+            Some(pattern) => {
+                cata_log!(Debug, format!("Skipping {} (matches pattern {})", relative_path.display(), pattern.as_str()));
+                true
+            }
+            None => false,
+        }
+    }
+
+    // Check whether a path lies inside an excluded directory such as node_modules. Only the part
+    // below its watch directory counts, so a watch directory that is itself named e.g. "dist" still works.
+    #[cfg(debug_assertions)]
+    fn is_in_excluded_dir(path: &Path, config: &VigilConfig) -> bool {
+        Self::path_in_watch_dir(path, config).components().any(|component| {
+            let name = component.as_os_str().to_string_lossy();
+            config.exclude_dirs.iter().any(|dir| name == dir.as_str())
+        })
+    }
+
+    // Check whether a path is an editor swap, backup or lock file, or hidden (a dotfile or inside a
+    // dot-directory) while `watch_hidden` is off. Like excluded directories, only the part below the
+    // watch directory counts. Editor artifacts are skipped even when hidden files are watched.
+    #[cfg(debug_assertions)]
+    fn is_hidden_or_temp_file(path: &Path, config: &VigilConfig) -> bool {
+        let file_name = path.file_name().map(|name| name.to_string_lossy()).unwrap_or_default();
+        let is_editor_temp = EDITOR_TEMP_SUFFIXES.iter().any(|suffix| file_name.ends_with(suffix))
+            || EDITOR_TEMP_PREFIXES.iter().any(|prefix| file_name.starts_with(prefix))
+            || EDITOR_TEMP_NAMES.contains(&&*file_name);
+        if is_editor_temp {
+            return true;
+        }
+
+        !config.watch_hidden && Self::path_in_watch_dir(path, config).components().any(|component| component.as_os_str().to_string_lossy().starts_with('.'))
+    }
+
+    // The part of a path below the watch directory containing it, or the project-relative path
+    // if it isn't inside any of them
+    #[cfg(debug_assertions)]
+    fn path_in_watch_dir(path: &Path, config: &VigilConfig) -> PathBuf {
+        let relative_path = Self::relative_path(path);
+
+        Self::watch_dirs(config)
+            .iter()
+            .find_map(|dir| relative_path.strip_prefix(Self::relative_path(dir)).ok().map(Path::to_path_buf))
+            .unwrap_or(relative_path)
+    }
+
+    // Check whether a path is excluded by the project's .gitignore files, unless it's in an overridden directory
+    #[cfg(debug_assertions)]
+    fn is_gitignored(path: &Path, config: &VigilConfig) -> bool {
+        let relative_path = Self::relative_path(path);
+        if config.gitignore_overrides.iter().any(|dir| relative_path.starts_with(Self::relative_path(dir))) {
+            return false;
+        }
+
+        let Ok(cwd) = std::env::current_dir() else {
+            return false;
+        };
+        let absolute_path = cwd.join(&relative_path);
+        let is_dir = absolute_path.is_dir();
+
+        // Deeper .gitignore files take precedence over their parents
+        let gitignores = GITIGNORES.get_or_init(|| Self::load_gitignores(config));
+        for gitignore in gitignores.iter().filter(|gitignore| absolute_path.starts_with(gitignore.path())) {
+            match gitignore.matched_path_or_any_parents(&absolute_path, is_dir) {
+                ignore::Match::Ignore(_) => {
+                    cata_log!(Debug, format!("Skipping {} (ignored by {})", relative_path.display(), gitignore.path().join(".gitignore").display()));
+                    return true;
+                }
+                ignore::Match::Whitelist(_) => return false,
+                ignore::Match::None => {}
+            }
+        }
+
+        false
+    }
+
+    // Load the project's .gitignore and any nested ones in or above the watch directories
+    #[cfg(debug_assertions)]
+    fn load_gitignores(config: &VigilConfig) -> Vec<Gitignore> {
+        let Ok(cwd) = std::env::current_dir() else {
+            return Vec::new();
+        };
+
+        let mut files = HashSet::new();
+        for dir in Self::watch_dirs(config) {
+            // Parents up to the project root, e.g. public/.gitignore for public/js
+            for ancestor in dir.ancestors().map(|ancestor| cwd.join(ancestor)).filter(|ancestor| ancestor.starts_with(&cwd)) {
+                let file = ancestor.join(".gitignore");
+                if file.is_file() {
+                    files.insert(file);
+                }
+            }
+
+            Self::find_gitignores(&cwd.join(&dir), &mut files);
+        }
+
+        let mut gitignores: Vec<Gitignore> = files
+            .into_iter()
+            .map(|file| {
+                let (gitignore, error) = Gitignore::new(&file);
+                if let Some(e) = error {
+                    cata_log!(Warning, format!("Could not fully parse {}: {}", file.display(), e));
+                }
+                gitignore
+            })
+            .collect();
+        gitignores.sort_by_key(|gitignore| std::cmp::Reverse(gitignore.path().components().count()));
+
+        cata_log!(Info, format!("Vigil: Loaded {} .gitignore files", gitignores.len()));
+        gitignores
+    }
+
+    // Collect .gitignore files below a directory
+    #[cfg(debug_assertions)]
+    fn find_gitignores(dir: &Path, files: &mut HashSet<PathBuf>) {
+        if let Ok(entries) = fs::read_dir(dir) {
+            for entry in entries.flatten() {
+                let path = entry.path();
+
+                if path.is_dir() {
+                    if path.file_name().map(|name| name != ".git").unwrap_or(true) {
+                        Self::find_gitignores(&path, files);
+                    }
+                } else if path.file_name().map(|name| name == ".gitignore").unwrap_or(false) {
+                    files.insert(path);
+                }
+            }
+        }
+    }
+
+    // Patterns are relative to the project root, while native events report absolute paths
+    #[cfg(debug_assertions)]
+    fn relative_path(path: &Path) -> PathBuf {
+        let relative_path = std::env::current_dir().ok().and_then(|cwd| path.strip_prefix(cwd).ok().map(Path::to_path_buf)).unwrap_or_else(|| path.to_path_buf());
+        relative_path.strip_prefix(".").map(Path::to_path_buf).unwrap_or(relative_path)
+    }
+
+    // URL of a project-relative file served from public/, e.g. "/css/app.css" for public/css/app.css
+    #[cfg(debug_assertions)]
+    fn public_url(relative_path: &Path) -> Option<String> {
+        let served_path = relative_path.strip_prefix(PUBLIC_DIR).ok()?;
+        Some(format!("{}/{}", Self::current_config().route_prefix, wire_path(served_path)))
+    }
+
+    // Rust sources require a rebuild rather than a plain reload
+    #[cfg(debug_assertions)]
+    fn is_rust_source(file_path: &Path) -> bool {
+        file_path.extension().map(|ext| ext == "rs").unwrap_or(false)
+    }
+
+    // Determine file type from extension for more helpful logging
+    #[cfg(debug_assertions)]
+    fn file_type(file_path: &Path) -> &'static str {
+        // Individually watched files are configuration that affects rendering, whatever their extension,
+        // and catalogs under the locales directory are translations even if they're plain JSON
+        let configured_type = ACTIVE_CONFIG.read().ok().as_ref().and_then(|config| config.as_ref()).and_then(|config| {
+            if Self::is_explicit_watch_file(file_path, config) {
+                Some("File")
+            } else if Self::is_translation_file(file_path, config) {
+                Some("Translation")
+            } else {
+                None
+            }
+        });
+        if let Some(file_type) = configured_type {
+            return file_type;
+        }
+
+        let ext = file_path.extension().map(|ext| ext.to_string_lossy().to_lowercase()).unwrap_or_default();
+
+        if TEMPLATE_EXTENSIONS.contains(&ext.as_str()) {
+            "Template"
+        } else if STYLESHEET_EXTENSIONS.contains(&ext.as_str()) {
+            "Stylesheet"
+        } else if SCRIPT_EXTENSIONS.contains(&ext.as_str()) {
+            "Script"
+        } else if Self::is_asset_extension(&ext) {
+            "Asset"
+        } else if Self::is_rust_source(file_path) {
+            "Rust source"
+        } else {
+            "File"
+        }
+    }
+
+    // Check if any watched file has been modified, created or deleted (polling fallback)
+    #[cfg(debug_assertions)]
+    #[cfg_attr(feature = "tracing", tracing::instrument)]
+    fn check_template_changes() -> DetectedChanges {
+        let config = Self::current_config();
+        if let Some(sentinel_file) = &config.sentinel_file {
+            return Self::check_sentinel_file(sentinel_file, config.dry_run);
+        }
+
+        let mut scan = DirectoryScan::default();
+        let mut changes = DetectedChanges::default();
+
+        // Reuse listings of directories that haven't changed since the last scan
+        if let Ok(mut listings) = DIRECTORY_LISTINGS.lock() {
+            scan.previous_listings = listings.take().unwrap_or_default();
+        }
+
+        // Skip directories that don't exist
+        let roots: Vec<PathBuf> = Self::watch_dirs(&config).into_iter().filter(|dir| dir.exists()).collect();
+        let parallel = config.parallel_scan && roots.len() > 1;
+        let started_at = std::time::Instant::now();
+
+        if parallel {
+            // Walk each directory on its own thread, with its share of the cached listings, and merge
+            // the results so every changed file is still attributed
+            let root_scans: Vec<DirectoryScan> = std::thread::scope(|scope| {
+                let handles: Vec<_> = roots
+                    .iter()
+                    .map(|dir| {
+                        let mut root_scan = DirectoryScan {
+                            previous_listings: scan.take_listings_below(dir),
+                            ..DirectoryScan::default()
+                        };
+                        let config = &config;
+                        scope.spawn(move || {
+                            Self::walk_directory(dir, config, 0, &mut root_scan);
+                            root_scan
+                        })
+                    })
+                    .collect();
+                handles.into_iter().filter_map(|handle| handle.join().ok()).collect()
+            });
+
+            for root_scan in root_scans {
+                scan.merge(root_scan);
+            }
+        } else {
+            for dir in &roots {
+                Self::walk_directory(dir, &config, 0, &mut scan);
+            }
+        }
+
+        // Report how long the baseline scan took, and any later scan that can't keep up with the refresh interval
+        let elapsed_ms = started_at.elapsed().as_millis();
+        METRICS.record_poll(elapsed_ms as u64);
+        let mode = if parallel { "parallel" } else { "serial" };
+        if KNOWN_FILES.lock().map(|known_files| known_files.is_none()).unwrap_or(false) {
+            cata_log!(Info, format!("Vigil scanned {} files in {}ms ({})", scan.seen_files.len(), elapsed_ms, mode));
+        } else if elapsed_ms > config.refresh_interval as u128 {
+            cata_log!(Debug, format!("Vigil scan of {} files took {}ms ({}), longer than the refresh interval", scan.seen_files.len(), elapsed_ms, mode));
+        }
+
+        // Individually watched files bypass the extension filter, missing ones are simply skipped
+        for file in &config.watch_files {
+            Self::stat_file(file, &config, &mut scan);
+        }
+
+        if let Ok(mut watched_file_count) = WATCHED_FILE_COUNT.lock() {
+            *watched_file_count = Some(scan.seen_files.len());
+        }
+
+        // A watch directory pointed at the project root walks far too many files to be usable. Skip
+        // change detection and name the directory holding most of them until the set shrinks again.
+        let max_files = config.max_watched_files as usize;
+        if max_files > 0 && (scan.limit_exceeded || scan.seen_files.len() > max_files) {
+            if !WATCH_LIMIT_EXCEEDED.swap(true, Ordering::SeqCst) {
+                let largest_root = roots.iter().max_by_key(|root| scan.seen_files.keys().filter(|path| path.starts_with(root)).count());
+                let message = format!(
+                    "Vigil found more than {} watched files ({} before stopping, mostly under {}), pausing change detection. Narrow watch_dirs or raise max_watched_files.",
+                    max_files,
+                    scan.seen_files.len(),
+                    largest_root.map(|root| root.display().to_string()).unwrap_or_default()
+                );
+                cata_log!(Error, message.clone());
+
+                // Tell open pages why they stopped reloading, sending only fails without clients
+                if let Some(sender) = CHANGE_BROADCAST.get() {
+                    let _ = sender.send(VigilMessage::Error { message });
+                }
+            }
+            return changes;
+        }
+        if WATCH_LIMIT_EXCEEDED.swap(false, Ordering::SeqCst) {
+            cata_log!(Info, format!("Vigil is watching {} files again, resuming change detection", scan.seen_files.len()));
+        }
+
+        if !scan.truncated_dirs.is_empty() && !TRUNCATION_REPORTED.swap(true, Ordering::SeqCst) {
+            cata_log!(Warning, format!("Vigil max_depth reached, not scanning below: {:?}", scan.truncated_dirs));
+        }
+
+        // Directories that weren't reached this time (deleted, now excluded) drop out of the cache
+        let DirectoryScan { latest_mod_time, seen_files, listings, .. } = scan;
+        if let Ok(mut cached_listings) = DIRECTORY_LISTINGS.lock() {
+            *cached_listings = Some(listings);
+        }
+
+        // Compare against the previous scan: new paths were created regardless of their timestamp
+        // (git checkout, cp -p), paths that are gone now were deleted, and any different modification
+        // time or size is a change, even an older time (git stash pop, restoring a backup) or the same
+        // time with new content (`cp --preserve=timestamps`, SCSS compilers rewriting within a second)
+        let last_time = LAST_MOD_TIME.load(Ordering::SeqCst);
+        if let Ok(mut known_files) = KNOWN_FILES.lock() {
+            match known_files.as_ref() {
+                Some(previous_files) => {
+                    let mut created: Vec<&PathBuf> = seen_files.keys().filter(|path| !previous_files.contains_key(*path)).collect();
+                    changes.modified = seen_files
+                        .iter()
+                        .filter(|(path, stamp)| previous_files.get(*path).map(|previous_stamp| previous_stamp != *stamp).unwrap_or(false))
+                        .map(|(path, _)| path.clone())
+                        .collect();
+
+                    // A rename keeps the modification time and size, so a vanished path and a new
+                    // path with the same stamp in one scan are reported as a single rename
+                    let mut deleted: Vec<PathBuf> = Vec::new();
+                    for (path, stamp) in previous_files.iter().filter(|(path, _)| !seen_files.contains_key(*path)) {
+                        match created.iter().position(|new_path| seen_files.get(*new_path) == Some(stamp)) {
+                            Some(index) => {
+                                let new_path = created.remove(index);
+                                changes.renamed.push((path.clone(), new_path.clone()));
+                            }
+                            None => deleted.push(path.clone()),
+                        }
+                    }
+
+                    changes.created = created.into_iter().cloned().collect();
+                    changes.deleted = Self::collapse_deleted(&deleted);
+                }
+                // The first scan takes the baseline, only reporting files saved since the detector started
+                None => {
+                    changes.modified = seen_files.iter().filter(|(_, stamp)| stamp.mod_time > last_time).map(|(path, _)| path.clone()).collect();
+                }
+            }
+            *known_files = Some(seen_files);
+        }
+        changes.modified.sort();
+
+        for created_file in &changes.created {
+            cata_log!(Debug, format!("{} creation detected: {}", Self::file_type(created_file), created_file.display()));
+        }
+
+        for deleted_file in &changes.deleted {
+            cata_log!(Debug, format!("{} deletion detected: {}", Self::file_type(deleted_file), deleted_file.display()));
+        }
+
+        for (old_file, new_file) in &changes.renamed {
+            cata_log!(Debug, format!("{} rename detected: {} -> {}", Self::file_type(new_file), old_file.display(), new_file.display()));
+        }
+
+        for changed_file in &changes.modified {
+            cata_log!(Debug, format!("{} change detected: {}", Self::file_type(changed_file), changed_file.display()));
+        }
+
+        #[cfg(feature = "tracing")]
+        if !changes.is_empty() {
+            tracing::info!(modified = ?changes.modified, created = ?changes.created, deleted = ?changes.deleted, renamed = ?changes.renamed, "change detected");
+        }
+
+        Self::advance_last_mod_time(latest_mod_time, config.dry_run);
+
+        changes
+    }
+
+    // Read the paths an external watcher wrote into the sentinel file since the last check, e.g. with
+    // `echo public/css/main.css > .vigil-changed`, and truncate it so each path is reported once.
+    // The file is only read when its modification time moved.
+    #[cfg(debug_assertions)]
+    fn check_sentinel_file(sentinel_file: &Path, dry_run: bool) -> DetectedChanges {
+        let mut changes = DetectedChanges::default();
+        let Some(mod_time) = Self::mod_time_millis(sentinel_file) else {
+            return changes;
+        };
+        if SENTINEL_MOD_TIME.swap(mod_time, Ordering::SeqCst) == mod_time {
+            return changes;
+        }
+
+        let contents = match fs::read_to_string(sentinel_file) {
+            Ok(contents) => contents,
+            Err(e) => {
+                cata_log!(Warning, format!("Could not read Vigil sentinel file {}: {}", sentinel_file.display(), e));
+                return changes;
+            }
+        };
+        if contents.trim().is_empty() {
+            return changes;
+        }
+
+        if let Err(e) = fs::write(sentinel_file, "") {
+            cata_log!(Warning, format!("Could not truncate Vigil sentinel file {}: {}", sentinel_file.display(), e));
+        }
+        // Truncating bumps the modification time again, which isn't a change of its own
+        if let Some(truncated_mod_time) = Self::mod_time_millis(sentinel_file) {
+            SENTINEL_MOD_TIME.store(truncated_mod_time, Ordering::SeqCst);
+        }
+
+        for path in contents.lines().map(str::trim).filter(|line| !line.is_empty()).map(PathBuf::from) {
+            if !changes.modified.contains(&path) {
+                cata_log!(Debug, format!("{} change signalled through {}: {}", Self::file_type(&path), sentinel_file.display(), path.display()));
+                changes.modified.push(path);
+            }
+        }
+
+        Self::advance_last_mod_time(mod_time, dry_run);
+        changes
+    }
+
+    // Keep the latest modification time for heartbeats and the status page. Clients reload when the
+    // heartbeat timestamp moves, so a dry run leaves it at its value from before the change.
+    #[cfg(debug_assertions)]
+    fn advance_last_mod_time(mod_time: u64, dry_run: bool) {
+        if !dry_run {
+            LAST_MOD_TIME.fetch_max(mod_time, Ordering::SeqCst);
+        }
+    }
+
+    #[cfg(debug_assertions)]
+    fn mod_time_millis(path: &Path) -> Option<u64> {
+        let modified = fs::metadata(path).and_then(|metadata| metadata.modified()).ok()?;
+        Some(modified.duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as u64)
+    }
+
+    // Reduce deleted paths to the highest removed ancestor, so deleting a
+    // directory wholesale produces a single event instead of one per file
+    #[cfg(debug_assertions)]
+    fn collapse_deleted(paths: &[PathBuf]) -> Vec<PathBuf> {
+        let mut roots: Vec<PathBuf> = Vec::new();
+
+        for path in paths {
+            let mut root = path.clone();
+            while let Some(parent) = root.parent() {
+                if parent.as_os_str().is_empty() || parent.exists() {
+                    break;
+                }
+                root = parent.to_path_buf();
+            }
+
+            if !roots.contains(&root) {
+                roots.push(root);
+            }
+        }
+
+        roots
+    }
+
+    // Drop modifications whose content is unchanged (touch, rsync, build tools bumping mtimes)
+    #[cfg(debug_assertions)]
+    fn filter_unchanged_content(mut changes: DetectedChanges) -> DetectedChanges {
+        let Ok(mut guard) = CONTENT_HASHES.lock() else {
+            return changes;
+        };
+        let hashes = guard.get_or_insert_with(HashMap::new);
+
+        // Deleted files (or whole directories) must not keep stale hashes around
+        for deleted_file in &changes.deleted {
+            hashes.retain(|path, _| !path.starts_with(deleted_file));
+        }
+
+        // The watched file set is usually small, but never let the cache grow without bound
+        if hashes.len() > HASH_CACHE_CAPACITY {
+            hashes.clear();
+        }
+
+        // Remember hashes of new files so a later touch doesn't trigger a reload
+        for created_file in &changes.created {
+            if let Ok(contents) = fs::read(created_file) {
+                hashes.insert(created_file.clone(), Self::content_hash(&contents));
+            }
+        }
+
+        changes.modified.retain(|modified_file| {
+            let Ok(contents) = fs::read(modified_file) else {
+                hashes.remove(modified_file);
+                return true;
+            };
+
+            // A file without a cached hash can't be compared, so it counts as changed
+            let hash = Self::content_hash(&contents);
+            let unchanged = hashes.insert(modified_file.clone(), hash) == Some(hash);
+            if unchanged {
+                cata_log!(Debug, format!("Ignoring {}: modification time changed but content did not", modified_file.display()));
+            }
+            !unchanged
+        });
+
+        changes
+    }
+
+    // BLAKE3, fast enough to run on every change and without the collisions of a short hash
+    #[cfg(debug_assertions)]
+    fn content_hash(bytes: &[u8]) -> [u8; 32] {
+        *blake3::hash(bytes).as_bytes()
+    }
+
+    // Build the messages sent to the client for a set of changed files. Each message is a JSON
+    // object with the action the client should take and every change it covers.
+    #[cfg(debug_assertions)]
+    fn change_messages(changes: &DetectedChanges) -> Vec<VigilMessage> {
+        let changed_files = &changes.modified;
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as u64;
+        let events = changes.events(timestamp);
+
+        let translation_files: Vec<PathBuf> = changed_files.iter().filter(|file| Self::file_type(file) == "Translation").cloned().collect();
+        let action = if changed_files.iter().any(|file| Self::is_rust_source(file)) {
+            // A Rust source change takes precedence since it needs a rebuild. The browser waits for
+            // the server to come back before reloading.
+            "rebuild"
+        } else if !changes.renamed.is_empty() || !changes.deleted.is_empty() || !changes.created.is_empty() {
+            // Renamed, deleted and new files always need a full reload, e.g. a stylesheet no page links to yet
+            "reload"
+        } else if !translation_files.is_empty() {
+            // Translations need a full reload, and the server a chance to drop cached catalogs
+            if let Ok(hooks) = TRANSLATION_HOOKS.lock() {
+                for hook in hooks.iter() {
+                    hook(&translation_files);
+                }
+            }
+            "i18n"
+        } else if changed_files.iter().all(|file| Self::file_type(file) == "Template") && Self::current_config().partial_reload {
+            // The page re-fetches itself and swaps its body, keeping scroll position and form input
+            "partial"
+        } else if changed_files.iter().all(|file| Self::file_type(file) == "Stylesheet") && Self::current_config().css_hot_swap {
+            // Stylesheets can be swapped in place as long as nothing else changed
+            "css-swap"
+        } else if changed_files.iter().all(|file| Self::file_type(file) == "Asset") {
+            // Images and fonts can be refreshed in place as well
+            "asset"
+        } else {
+            "reload"
+        };
+
+        let descriptions: Vec<String> = events.iter().map(ChangeEvent::describe).collect();
+        cata_log!(Info, format!("{}, sending {} signal", descriptions.join(", "), action));
+
+        vec![VigilMessage::reload(action, events)]
+    }
+
+    // Helper function to recursively walk directories, `depth` counting levels below the watch root
+    #[cfg(debug_assertions)]
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip(dir, config, scan), fields(dir = %dir.display())))]
+    fn walk_directory(dir: &Path, config: &VigilConfig, depth: u32, scan: &mut DirectoryScan) {
+        if scan.limit_exceeded {
+            return;
+        }
+
+        // With symlinks followed, each directory is walked once by canonical path, so links pointing
+        // back up the tree can't recurse forever and a linked directory isn't scanned twice
+        if config.follow_symlinks && !dir.canonicalize().map(|canonical| scan.visited_dirs.insert(canonical)).unwrap_or(false) {
+            return;
+        }
+
+        let Some(listing) = Self::list_directory(dir, config, &mut scan.previous_listings) else {
+            return;
+        };
+
+        // Real subdirectories go first, so their files are reported under their own paths rather
+        // than through a link to them
+        let subdirs = listing.subdirs.iter().filter(|(_, is_symlink)| !is_symlink).chain(listing.subdirs.iter().filter(|(_, is_symlink)| *is_symlink));
+        for (path, is_symlink) in subdirs {
+            // Symlinked directories are only entered when enabled
+            if *is_symlink && !config.follow_symlinks {
+                continue;
+            }
+
+            if config.max_depth.map(|max_depth| depth >= max_depth).unwrap_or(false) {
+                scan.truncated_dirs.push(path.clone());
+                continue;
+            }
+
+            // Recursively walk subdirectories
+            Self::walk_directory(path, config, depth + 1, scan);
+        }
+
+        // Files are stat'ed on every scan, since editing a file in place doesn't touch its directory
+        for path in &listing.files {
+            if config.max_watched_files > 0 && scan.seen_files.len() >= config.max_watched_files as usize {
+                scan.limit_exceeded = true;
+                return;
+            }
+            Self::stat_file(path, config, scan);
+        }
+
+        scan.listings.insert(dir.to_path_buf(), listing);
+    }
+
+    // Record a file's modification time and size in the scan, skipping it if it doesn't exist (anymore)
+    // or is larger than `max_watch_file_size_kb`
+    #[cfg(debug_assertions)]
+    fn stat_file(path: &Path, config: &VigilConfig, scan: &mut DirectoryScan) {
+        // fs::metadata follows symlinks, so symlinked files report their target's modification time.
+        // Millisecond resolution catches repeated saves within the same second. Filesystems with
+        // coarser timestamps just report whole seconds, which never differ from an identical
+        // earlier value, so they can't cause spurious reloads.
+        let Ok(metadata) = fs::metadata(path) else {
+            return;
+        };
+        if !metadata.is_file() {
+            return;
+        }
+
+        // Bundled vendor files can be tens of megabytes and are rarely edited by hand
+        if config.max_watch_file_size_kb > 0 && metadata.len() > config.max_watch_file_size_kb * 1024 {
+            if !LARGE_FILE_REPORTED.swap(true, Ordering::SeqCst) {
+                cata_log!(Warning, format!("Vigil not watching {} ({} KiB), it exceeds max_watch_file_size_kb = {}", path.display(), metadata.len() / 1024, config.max_watch_file_size_kb));
+            }
+            return;
+        }
+
+        let millis = metadata.modified().ok().and_then(|mod_time| mod_time.duration_since(UNIX_EPOCH).ok()).map(|since_epoch| since_epoch.as_millis() as u64).unwrap_or(0);
+
+        #[cfg(feature = "tracing")]
+        tracing::debug!(file = %path.display(), mod_time = millis, "stat");
+
+        // Update latest mod time if newer
+        scan.latest_mod_time = scan.latest_mod_time.max(millis);
+        // Unreadable files still count as present, they just can't be compared by content
+        let hash = match config.change_detection {
+            ChangeDetection::Hash => fs::read(path).ok().map(|contents| Self::content_hash(&contents)),
+            ChangeDetection::Mtime => None,
+        };
+        scan.seen_files.insert(path.to_path_buf(), FileStamp { mod_time: millis, size: metadata.len(), hash });
+    }
+
+    // List a directory's watched files and subdirectories, reusing the previous listing if the
+    // directory hasn't changed since. This skips read_dir and the ignore checks for most directories.
+    #[cfg(debug_assertions)]
+    fn list_directory(dir: &Path, config: &VigilConfig, previous_listings: &mut HashMap<PathBuf, DirectoryListing>) -> Option<DirectoryListing> {
+        let mod_time = fs::metadata(dir).and_then(|metadata| metadata.modified()).ok()?;
+
+        if let Some(listing) = previous_listings.remove(dir) {
+            let settled = listing.listed_at.duration_since(mod_time).map(|age| age.as_millis() as u64 >= DIRECTORY_LISTING_SETTLE_MS).unwrap_or(false);
+            if listing.mod_time == mod_time && settled {
+                return Some(listing);
+            }
+        }
+
+        let entries = fs::read_dir(dir).ok()?;
+        let mut listing = DirectoryListing {
+            mod_time,
+            listed_at: SystemTime::now(),
+            subdirs: Vec::new(),
+            files: Vec::new(),
+        };
+
+        for entry in entries.flatten() {
+            let path = entry.path();
+
+            let is_symlink = entry.file_type().map(|file_type| file_type.is_symlink()).unwrap_or(false);
+            let Ok(metadata) = fs::metadata(&path) else {
+                // Broken symlink
+                continue;
+            };
+
+            if metadata.is_dir() {
+                // Skip ignored subtrees entirely, they don't count as truncated
+                if !Self::is_ignored(&path, config) {
+                    listing.subdirs.push((path, is_symlink));
+                }
+            } else if metadata.is_file() && Self::is_watched_file(&path, config) && !Self::is_ignored(&path, config) {
+                listing.files.push(path);
+            }
+        }
+
+        Some(listing)
+    }
+}
+
+// Native file watcher feeding filesystem events to the shared change detector
+#[cfg(debug_assertions)]
+struct FileWatcher {
+    // Kept alive so events keep flowing for as long as the detector runs
+    _watcher: RecommendedWatcher,
+    events: mpsc::Receiver<notify::Event>,
+}
+
+#[cfg(debug_assertions)]
+impl FileWatcher {
+    // Start watching all configured directories
+    fn new(config: &VigilConfig) -> notify::Result<Self> {
+        let (sender, events) = mpsc::channel(EVENT_QUEUE_CAPACITY);
+        let filter_config = config.clone();
+
+        let mut watcher = notify::recommended_watcher(move |result: notify::Result<notify::Event>| match result {
+            Ok(event) => {
+                // Only creations, modifications and removals of watched files are interesting
+                if !matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_) | EventKind::Remove(_)) {
+                    return;
+                }
+
+                if event.paths.iter().any(|path| VigilSpark::is_watched_file(path, &filter_config)) {
+                    // A full queue already guarantees a pending reload, so dropping is fine
+                    let _ = sender.try_send(event);
+                }
+            }
+            Err(e) => cata_log!(Warning, format!("Vigil file watcher error: {}", e)),
+        })?;
+
+        for dir in VigilSpark::watch_dirs(config) {
+            // Skip if directory doesn't exist
+            if !dir.exists() {
+                continue;
+            }
+
+            // A failure here usually means the inotify watch limit was hit
+            watcher.watch(&dir, RecursiveMode::Recursive)?;
+        }
+
+        // Watch the parent directory of individual files, since editors that save atomically replace
+        // the file itself. Events for its other entries fail the is_watched_file check above.
+        let mut file_dirs: Vec<PathBuf> = Vec::new();
+        for file in &config.watch_files {
+            let parent = file.parent().filter(|parent| !parent.as_os_str().is_empty()).unwrap_or(Path::new(".")).to_path_buf();
+            if parent.exists() && !file_dirs.contains(&parent) {
+                watcher.watch(&parent, RecursiveMode::NonRecursive)?;
+                file_dirs.push(parent);
+            }
+        }
+
+        Ok(Self { _watcher: watcher, events })
+    }
+
+    // Coalesce events arriving within the debounce window into a single set of changes
+    async fn collect_changes(&mut self, first_event: notify::Event, config: &VigilConfig) -> DetectedChanges {
+        let mut batch = vec![first_event];
+        let deadline = rocket::tokio::time::Instant::now() + std::time::Duration::from_millis(config.debounce_ms as u64);
+        while let Ok(Some(event)) = rocket::tokio::time::timeout_at(deadline, self.events.recv()).await {
+            batch.push(event);
+        }
+
+        let mut changes = DetectedChanges::default();
+        let mut created_paths: Vec<PathBuf> = Vec::new();
+        let mut removed_paths: Vec<PathBuf> = Vec::new();
+        let mut touched_paths: Vec<PathBuf> = Vec::new();
+        for event in batch {
+            // Backends that see both ends of a rename report them in one event as [old, new]
+            if let (EventKind::Modify(ModifyKind::Name(RenameMode::Both)), [old_path, new_path]) = (&event.kind, event.paths.as_slice()) {
+                let is_watched = |path: &Path| VigilSpark::is_watched_file(path, config) && !VigilSpark::is_ignored(path, config);
+                if is_watched(old_path) || is_watched(new_path) {
+                    changes.renamed.push((old_path.clone(), new_path.clone()));
+                }
+                continue;
+            }
+
+            for path in event.paths.into_iter().filter(|path| VigilSpark::is_watched_file(path, config) && !VigilSpark::is_ignored(path, config)) {
+                match event.kind {
+                    EventKind::Create(_) => created_paths.push(path.clone()),
+                    EventKind::Remove(_) => removed_paths.push(path.clone()),
+                    _ => {}
+                }
+                if !touched_paths.contains(&path) {
+                    touched_paths.push(path);
+                }
+            }
+        }
+
+        // Editors that save atomically remove and recreate the file, so a path only counts as
+        // created if it wasn't also removed, and only counts as deleted if it's gone from disk now
+        for path in touched_paths.into_iter().filter(|path| path.exists()) {
+            if created_paths.contains(&path) && !removed_paths.contains(&path) {
+                changes.created.push(path);
+            } else {
+                changes.modified.push(path);
+            }
+        }
+        removed_paths.retain(|path| !path.exists());
+        changes.deleted = VigilSpark::collapse_deleted(&removed_paths);
+
+        changes
+    }
+}
+
+// Change detection running once in a background task, publishing to all
+// WebSocket and SSE clients so every connection sees every change
+#[cfg(debug_assertions)]
+struct ChangeDetector {
+    config: VigilConfig,
+    // Native watcher, or None when falling back to polling
+    file_watcher: Option<FileWatcher>,
+    // Modification time of Catalyst.toml when the config was last loaded
+    catalyst_mod_time: Option<SystemTime>,
+    // Watch directories that didn't exist when the native watcher started
+    missing_dirs: Vec<PathBuf>,
+}
+
+#[cfg(debug_assertions)]
+impl ChangeDetector {
+    fn new(config: VigilConfig) -> Self {
+        // Set the initial timestamp to now instead of 0 to avoid fake changes
+        let current_time = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as u64;
+        LAST_MOD_TIME.store(current_time, Ordering::SeqCst);
+
+        let file_watcher = Self::start_file_watcher(&config);
+        let catalyst_mod_time = Self::catalyst_mod_time();
+        let missing_dirs = Self::missing_dirs(&config);
+
+        Self { config, file_watcher, catalyst_mod_time, missing_dirs }
+    }
+
+    fn missing_dirs(config: &VigilConfig) -> Vec<PathBuf> {
+        VigilSpark::watch_dirs(config).into_iter().filter(|dir| !dir.exists()).collect()
+    }
+
+    // The native watcher can only watch existing directories, so restart it once a missing one
+    // appears. Polling checks every directory on each scan anyway.
+    fn restart_watcher_if_dirs_appeared(&mut self) {
+        if self.file_watcher.is_none() || !self.missing_dirs.iter().any(|dir| dir.exists()) {
+            return;
+        }
+
+        for dir in self.missing_dirs.iter().filter(|dir| dir.exists()) {
+            cata_log!(Info, format!("Vigil watch directory {} appeared, restarting the file watcher", dir.display()));
+        }
+        self.file_watcher = Self::start_file_watcher(&self.config);
+        self.missing_dirs = Self::missing_dirs(&self.config);
+    }
+
+    // Prefer native filesystem events, falling back to polling if the watcher can't start
+    fn start_file_watcher(config: &VigilConfig) -> Option<FileWatcher> {
+        // The external watcher already knows what changed, polling the sentinel file is all that's left
+        if let Some(sentinel_file) = &config.sentinel_file {
+            cata_log!(Info, format!("Vigil sentinel_file = {}, polling it instead of watching directories", sentinel_file.display()));
+            return None;
+        }
+
+        // Filesystems with unreliable modification times rarely deliver native events either
+        if config.change_detection == ChangeDetection::Hash {
+            cata_log!(Info, "Vigil change_detection = \"hash\", polling and hashing watched files instead of using native file events");
+            return None;
+        }
+
+        match FileWatcher::new(config) {
+            Ok(file_watcher) => Some(file_watcher),
+            Err(e) => {
+                cata_log!(Warning, format!("Could not start native file watcher, falling back to polling: {}", e));
+                None
+            }
+        }
+    }
+
+    fn catalyst_mod_time() -> Option<SystemTime> {
+        fs::metadata("Catalyst.toml").and_then(|metadata| metadata.modified()).ok()
+    }
+
+    // Pick up edits to Catalyst.toml without restarting the server
+    fn reload_config_if_changed(&mut self) {
+        if !FOLLOW_CATALYST_TOML.load(Ordering::SeqCst) {
+            return;
+        }
+
+        let catalyst_mod_time = Self::catalyst_mod_time();
+        if catalyst_mod_time == self.catalyst_mod_time {
+            return;
+        }
+        self.catalyst_mod_time = catalyst_mod_time;
+
+        let config = VigilSpark::load_config();
+        for (key, old_value, new_value) in VigilSpark::config_changes(&self.config, &config) {
+            cata_log!(Info, format!("Vigil config reloaded: {} {} -> {}", key, old_value, new_value));
+        }
+
+        // Routes are mounted once at startup, so switching environments needs a restart
+        let environment = VigilSpark::get_environment();
+        if let Some(instance) = vigil_config().filter(|instance| instance.environment != environment) {
+            cata_log!(Warning, format!("Ignoring environment change from {} to {} until the server restarts", instance.environment, environment));
+        }
+
+        if let Ok(mut active_config) = ACTIVE_CONFIG.write() {
+            *active_config = Some(config.clone());
+        }
+
+        // Cached scan results were filtered with the old rules, so take a fresh baseline
+        if let Ok(mut listings) = DIRECTORY_LISTINGS.lock() {
+            *listings = None;
+        }
+        if let Ok(mut known_files) = KNOWN_FILES.lock() {
+            *known_files = None;
+        }
+        LAST_MOD_TIME.store(SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as u64, Ordering::SeqCst);
+
+        // Watch directories and filters may have changed
+        self.file_watcher = Self::start_file_watcher(&config);
+        self.missing_dirs = Self::missing_dirs(&config);
+        self.config = config;
+
+        // Let clients know intervals and filters may have changed, sending only fails without clients
+        if let Some(sender) = CHANGE_BROADCAST.get() {
+            let _ = sender.send(VigilMessage::ConfigReloaded);
+        }
+    }
+
+    // Subscribe to reload messages from the shared detector, starting it on first use. The detector
+    // stops once Rocket shuts down, dropping the native watcher and its file handles.
+    fn subscribe(shutdown: Shutdown) -> broadcast::Receiver<VigilMessage> {
+        CHANGE_BROADCAST
+            .get_or_init(|| {
+                let (sender, _) = broadcast::channel(CHANGE_BROADCAST_CAPACITY);
+                let task_sender = sender.clone();
+
+                rocket::tokio::spawn(async move {
+                    let mut detector = ChangeDetector::new(VigilSpark::current_config());
+                    let mut shutdown = shutdown;
+                    cata_log!(Info, "Vigil: Change detector started");
+
+                    loop {
+                        let next_changes = rocket::tokio::select! {
+                            changes = detector.next_changes() => changes,
+                            _ = &mut shutdown => {
+                                let _ = task_sender.send(VigilMessage::Shutdown);
+                                cata_log!(Info, "Vigil: Change detector stopped");
+                                break;
+                            }
+                        };
+
+                        if let Some(changes) = next_changes {
+                            // Messages are built once so every tab sees exactly the same signals
+                            for message in VigilSpark::change_messages(&changes) {
+                                if detector.config.dry_run {
+                                    cata_log!(Debug, format!("Vigil dry run, not sending: {}", message.to_text(PROTOCOL_VERSION)));
+                                    continue;
+                                }
+
+                                // Sending only fails while no client is connected
+                                if matches!(message, VigilMessage::Reload { .. }) {
+                                    METRICS.record_reload();
+                                }
+                                message.remember();
+                                let _ = task_sender.send(message);
+                            }
+                        }
+                    }
+                });
+
+                sender
+            })
+            .subscribe()
+    }
+
+    // Files the last polling scan found, or a one-off scan when native events are used and
+    // nothing is polled. The caches of the polling scanner are left alone.
+    fn watched_files() -> (bool, HashMap<PathBuf, FileStamp>) {
+        if let Some(known_files) = KNOWN_FILES.lock().ok().and_then(|known_files| known_files.clone()) {
+            return (false, known_files);
+        }
+
+        let config = VigilSpark::current_config();
+        let mut scan = DirectoryScan::default();
+        for dir in VigilSpark::watch_dirs(&config).iter().filter(|dir| dir.exists()) {
+            VigilSpark::walk_directory(dir, &config, 0, &mut scan);
+        }
+        for file in &config.watch_files {
+            VigilSpark::stat_file(file, &config, &mut scan);
+        }
+        (true, scan.seen_files)
+    }
+
+    // Add published changes to the change log shown on the status page
+    fn record_changes(timestamp: u64, changes: &DetectedChanges) {
+        if let Ok(mut change_log) = CHANGE_LOG.lock() {
+            for event in changes.events(timestamp) {
+                if change_log.len() == CHANGE_LOG_CAPACITY {
+                    change_log.pop_front();
+                }
+                change_log.push_back(event);
+            }
+        }
+    }
+
+    // Scan the watched directories once without blocking the runtime, None if the scan failed
+    async fn poll_changes() -> Option<DetectedChanges> {
+        let check_result = rocket::tokio::task::spawn_blocking(VigilSpark::check_template_changes);
+        match rocket::tokio::time::timeout(std::time::Duration::from_secs(5), check_result).await {
+            Ok(Ok(changes)) => Some(changes),
+            Ok(Err(error)) => {
+                cata_log!(Warning, format!("Vigil scan failed: {}", error));
+                None
+            }
+            Err(_) => {
+                cata_log!(Warning, "Vigil scan took longer than 5 seconds, e.g. on a slow network mount");
+                None
+            }
+        }
+    }
+
+    // Back off after a failed scan, doubling the wait from the refresh interval up to
+    // SCAN_BACKOFF_CAP_MS. Scanning never stops, pages are told once `max_errors` failed in a row.
+    fn record_scan_error(&self) {
+        let errors = SCAN_ERRORS.fetch_add(1, Ordering::SeqCst) + 1;
+        let backoff = (self.config.refresh_interval as u64).saturating_mul(1 << errors.min(16)).min(SCAN_BACKOFF_CAP_MS);
+        SCAN_BACKOFF_MS.store(backoff, Ordering::SeqCst);
+        cata_log!(Debug, format!("Vigil scan failed {} times in a row, next scan in {}ms", errors, backoff));
+
+        if self.config.max_errors > 0 && errors == self.config.max_errors {
+            let message = format!("Vigil scans failed {} times in a row, still retrying every {}ms at most. Changes may be missed until they succeed.", errors, SCAN_BACKOFF_CAP_MS);
+            cata_log!(Error, message.clone());
+
+            // Sending only fails without clients
+            if let Some(sender) = CHANGE_BROADCAST.get() {
+                let _ = sender.send(VigilMessage::Error { message });
+            }
+        }
+    }
+
+    fn record_scan_success() {
+        SCAN_BACKOFF_MS.store(0, Ordering::SeqCst);
+        let errors = SCAN_ERRORS.swap(0, Ordering::SeqCst);
+        if errors > 0 {
+            cata_log!(Info, format!("Vigil scan succeeded again after {} failures", errors));
+        }
+    }
+
+    // Wait for the next set of changes. Returns None if nothing changed within
+    // one heartbeat (native events) or refresh interval (polling).
+    async fn next_changes(&mut self) -> Option<DetectedChanges> {
+        self.reload_config_if_changed();
+        self.restart_watcher_if_dirs_appeared();
+
+        let changes = if let Some(file_watcher) = self.file_watcher.as_mut() {
+            // Event-driven detection
+            match rocket::tokio::time::timeout(std::time::Duration::from_millis(HEARTBEAT_INTERVAL_MS), file_watcher.events.recv()).await {
+                Ok(Some(first_event)) => Some(file_watcher.collect_changes(first_event, &self.config).await),
+                Ok(None) => {
+                    cata_log!(Warning, "Native file watcher stopped, falling back to polling");
+                    self.file_watcher = None;
+                    None
+                }
+                Err(_) => None,
+            }
+        } else {
+            // Polling detection, backing off while the watch set is too large to scan
+            let refresh_interval = if WATCH_LIMIT_EXCEEDED.load(Ordering::SeqCst) { WATCH_LIMIT_RETRY_MS.max(self.config.refresh_interval as u64) } else { self.config.refresh_interval as u64 };
+            let refresh_interval = refresh_interval.max(SCAN_BACKOFF_MS.load(Ordering::SeqCst));
+            rocket::tokio::time::sleep(std::time::Duration::from_millis(refresh_interval)).await;
+
+            // Perform file check in a background task
+            let scanned = Self::poll_changes().await;
+            match scanned {
+                Some(_) => Self::record_scan_success(),
+                None => self.record_scan_error(),
+            }
+
+            match scanned.filter(|changes| !changes.is_empty()) {
+                Some(mut changes) => {
+                    // Give related writes (e.g. a SCSS build) one debounce window to land in the same batch
+                    rocket::tokio::time::sleep(std::time::Duration::from_millis(self.config.debounce_ms as u64)).await;
+                    if let Some(later_changes) = Self::poll_changes().await {
+                        changes.merge(later_changes);
                     }
+                    Some(changes)
                 }
+                None => None,
             }
+        };
 
-            // Also check at root level for backward compatibility
-            if let Some(value) = manifest.get(key) {
-                if let Some(int_value) = value.as_integer() {
-                    return int_value;
+        let mut changes = changes.filter(|changes| !changes.is_empty())?;
+
+        // Optionally confirm that the content actually changed
+        if self.config.hash_check {
+            changes = rocket::tokio::task::spawn_blocking(move || VigilSpark::filter_unchanged_content(changes)).await.ok()?;
+            if changes.is_empty() {
+                return None;
+            }
+        }
+
+        // Editors often save in several writes, so changes within the cooldown of the last reload
+        // are held back and sent once it ends rather than dropped
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as u64;
+        let cooldown_end = LAST_RELOAD_TIME.load(Ordering::SeqCst) + self.config.cooldown_period as u64;
+        if now < cooldown_end {
+            cata_log!(Debug, format!("Queueing reload until cooldown period ends: {:?}", changes.modified));
+            rocket::tokio::time::sleep(std::time::Duration::from_millis(cooldown_end - now)).await;
+
+            // Anything that changed while waiting goes out with the same reload
+            if let Some(file_watcher) = self.file_watcher.as_mut() {
+                if let Ok(event) = file_watcher.events.try_recv() {
+                    changes.merge(file_watcher.collect_changes(event, &self.config).await);
                 }
+            } else if let Some(later_changes) = Self::poll_changes().await {
+                changes.merge(later_changes);
             }
         }
-        default
+
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as u64;
+        LAST_RELOAD_TIME.store(now, Ordering::SeqCst);
+        Self::record_changes(now, &changes);
+
+        Some(changes)
     }
+}
 
-    // Helper to get a boolean config value with fallback to environment and default
-    fn get_config_bool(toml_config: &Option<toml::Value>, key: &str, env_key: &str, default: bool) -> bool {
-        use std::env;
+// Messages a WebSocket client can send, as JSON tagged with `type`
+#[cfg(debug_assertions)]
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+enum ClientMessage {
+    // Only receive changes to paths matching one of these globs, relative to the project root.
+    // An empty list receives every change again.
+    Subscribe { paths: Vec<String> },
+    // Ask for server stats, answered with a healthcheck message. A bare `healthcheck` works too.
+    Healthcheck,
+}
 
-        toml_config
-            .as_ref()
-            .and_then(|c| c.get("spark"))
-            .and_then(|s| s.get("vigil"))
-            .and_then(|p| p.get(key))
-            .and_then(|v| v.as_bool())
-            .unwrap_or_else(|| env::var(env_key).unwrap_or_else(|_| default.to_string()).parse().unwrap_or(default))
+#[cfg(debug_assertions)]
+impl ClientMessage {
+    // None for anything that isn't a known message
+    fn parse(connection_id: u32, text: &str) -> Option<Self> {
+        if text.trim() == "healthcheck" {
+            return Some(ClientMessage::Healthcheck);
+        }
+
+        let message = serde_json::from_str::<ClientMessage>(text).ok();
+        if message.is_none() {
+            cata_log!(Debug, format!("[id={}] Ignoring client message: {}", connection_id, text));
+        }
+        message
     }
 
-    // Helper to get an integer config value with fallback to environment and default
-    fn get_config_integer(toml_config: &Option<toml::Value>, key: &str, env_key: &str, default: i64) -> i64 {
-        use std::env;
+    // Compiled globs of a subscribe message
+    fn subscription(connection_id: u32, paths: &[String]) -> Vec<glob::Pattern> {
+        cata_log!(Info, format!("[id={}] Subscribed to {}", connection_id, if paths.is_empty() { String::from("all changes") } else { paths.join(", ") }));
+        VigilSpark::compile_patterns(paths)
+    }
 
-        toml_config
-            .as_ref()
-            .and_then(|c| c.get("spark"))
-            .and_then(|s| s.get("vigil"))
-            .and_then(|p| p.get(key))
-            .and_then(|v| v.as_integer())
-            .unwrap_or_else(|| env::var(env_key).unwrap_or_else(|_| default.to_string()).parse().unwrap_or(default))
+    fn healthcheck(connection_id: u32) -> VigilMessage {
+        cata_log!(Debug, format!("[id={}] Answering healthcheck", connection_id));
+        VigilMessage::Healthcheck {
+            uptime_secs: STARTED_AT.get().map(|started_at| started_at.elapsed().as_secs()).unwrap_or(0),
+            total_reloads: METRICS.total_reloads.load(Ordering::Relaxed),
+            connected_clients: ConnectedClient::all().len(),
+            server_time: SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as u64,
+        }
     }
+}
 
-    // Helper to get the current environment from Catalyst.toml
-    fn get_environment() -> String {
-        let config_path = "Catalyst.toml";
-        let config_str = fs::read_to_string(config_path).unwrap_or_default();
+// What woke up a WebSocket connection's loop
+#[cfg(debug_assertions)]
+enum ConnectionEvent {
+    Broadcast(Result<VigilMessage, broadcast::error::RecvError>),
+    // No message for a heartbeat interval
+    Heartbeat,
+    // Time to send a ping frame
+    Keepalive,
+    // The client sent a message
+    Client(ClientMessage),
+}
 
-        if !config_str.is_empty() {
-            if let Ok(toml) = toml::from_str::<toml::Value>(&config_str) {
-                if let Some(settings) = toml.get("settings") {
-                    if let Some(env) = settings.get("environment") {
-                        if let Some(env_str) = env.as_str() {
-                            return env_str.to_string();
-                        }
-                    }
-                }
+// A counted WebSocket connection, released when its stream is dropped however it ended
+#[cfg(debug_assertions)]
+struct ConnectionSlot;
+
+#[cfg(debug_assertions)]
+impl ConnectionSlot {
+    // Claim a slot unless `limit` connections are already open, 0 allows any number
+    fn acquire(limit: u32) -> Option<Self> {
+        WS_CONNECTIONS.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |open| (limit == 0 || open < limit).then_some(open + 1)).ok().map(|_| ConnectionSlot)
+    }
+}
+
+#[cfg(debug_assertions)]
+impl Drop for ConnectionSlot {
+    fn drop(&mut self) {
+        WS_CONNECTIONS.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+// Who opened a reload WebSocket, from the upgrade request
+#[cfg(debug_assertions)]
+#[derive(Clone, Serialize)]
+struct ConnectedClient {
+    address: Option<std::net::IpAddr>,
+    user_agent: Option<String>,
+    // Milliseconds since the epoch
+    connected_at: u64,
+    // Sequence number of the latest reload sent to the client
+    last_seq: u64,
+}
+
+#[cfg(debug_assertions)]
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for ConnectedClient {
+    type Error = std::convert::Infallible;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        Outcome::Success(ConnectedClient {
+            address: DevClientGuard::client_address(request, &VigilSpark::current_config()).map(|ip| ip.to_canonical()),
+            user_agent: request.headers().get_one("User-Agent").map(str::to_string),
+            connected_at: 0,
+            last_seq: 0,
+        })
+    }
+}
+
+#[cfg(debug_assertions)]
+impl ConnectedClient {
+    fn describe(&self) -> String {
+        let address = self.address.map(|address| address.to_string()).unwrap_or_else(|| String::from("unknown address"));
+        format!("{} ({})", address, self.user_agent.as_deref().unwrap_or("no user agent"))
+    }
+
+    // Snapshot of the connected clients by connection id
+    fn all() -> Vec<(u32, ConnectedClient)> {
+        let mut clients: Vec<(u32, ConnectedClient)> = CONNECTED_CLIENTS.lock().ok().and_then(|clients| clients.as_ref().map(|clients| clients.iter().map(|(id, client)| (*id, client.clone())).collect())).unwrap_or_default();
+        clients.sort_by_key(|(id, _)| *id);
+        clients
+    }
+}
+
+// Entry in CONNECTED_CLIENTS, removed when the stream is dropped however it ended
+#[cfg(debug_assertions)]
+struct ClientRegistration {
+    connection_id: u32,
+}
+
+#[cfg(debug_assertions)]
+impl ClientRegistration {
+    fn register(connection_id: u32, mut client: ConnectedClient) -> Self {
+        client.connected_at = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as u64;
+        client.last_seq = VigilMessage::last_sequence();
+        cata_log!(Info, format!("[id={}] WebSocket client connected from {}", connection_id, client.describe()));
+
+        if let Ok(mut clients) = CONNECTED_CLIENTS.lock() {
+            clients.get_or_insert_with(HashMap::new).insert(connection_id, client);
+        }
+        ClientRegistration { connection_id }
+    }
+
+    fn record_sequence(&self, seq: u64) {
+        cata_log!(Debug, format!("[id={}] Sent reload {}", self.connection_id, seq));
+        if let Ok(mut clients) = CONNECTED_CLIENTS.lock() {
+            if let Some(client) = clients.as_mut().and_then(|clients| clients.get_mut(&self.connection_id)) {
+                client.last_seq = client.last_seq.max(seq);
             }
         }
+    }
+}
 
-        // Default to production if not specified
-        "prod".to_string()
+#[cfg(debug_assertions)]
+impl Drop for ClientRegistration {
+    fn drop(&mut self) {
+        let client = CONNECTED_CLIENTS.lock().ok().and_then(|mut clients| clients.as_mut().and_then(|clients| clients.remove(&self.connection_id)));
+        if let Some(client) = client {
+            let connected_ms = (SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as u64).saturating_sub(client.connected_at);
+            cata_log!(Info, format!("[id={}] WebSocket client disconnected from {} after {}s", self.connection_id, client.describe(), connected_ms / 1000));
+        }
     }
+}
 
-    // Check if any watched file has been modified
-    fn check_template_changes() -> Option<String> {
-        let mut latest_mod_time = 0;
-        let mut changed_file = None;
+// Sub-protocols listed in the client's Sec-WebSocket-Protocol header, empty if it didn't send any
+#[cfg(debug_assertions)]
+struct RequestedProtocols(Vec<String>);
 
-        // Watch several directories for changes
-        let watch_dirs = [
-            Path::new("templates"),  // Template files
-            Path::new("public/css"), // CSS files
-            Path::new("public/js"),  // JavaScript files
-            Path::new("src/assets"), // Source assets (SCSS, TS, etc.)
-        ];
+#[cfg(debug_assertions)]
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for RequestedProtocols {
+    type Error = std::convert::Infallible;
 
-        // Walk each directory recursively
-        for dir in watch_dirs.iter() {
-            // Skip if directory doesn't exist
-            if !dir.exists() {
-                continue;
-            }
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let protocols = request.headers().get("Sec-WebSocket-Protocol").flat_map(|value| value.split(',')).map(|protocol| protocol.trim().to_string()).filter(|protocol| !protocol.is_empty()).collect();
+        Outcome::Success(RequestedProtocols(protocols))
+    }
+}
+
+// The reload WebSocket's request guards in one, run in order: the dev client and token checks, the
+// Origin check, then what the handshake asked for and who sent it
+#[cfg(debug_assertions)]
+struct ReloadSocketGuards {
+    requested: RequestedProtocols,
+    client: ConnectedClient,
+}
+
+#[cfg(debug_assertions)]
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for ReloadSocketGuards {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        rocket::outcome::try_outcome!(request.guard::<DevClientGuard>().await);
+        rocket::outcome::try_outcome!(request.guard::<DevToken>().await);
+        rocket::outcome::try_outcome!(request.guard::<AllowedOrigin>().await);
+        let requested = rocket::outcome::try_outcome!(request.guard::<RequestedProtocols>().await.map_error(|(status, _)| (status, ())));
+        let client = rocket::outcome::try_outcome!(request.guard::<ConnectedClient>().await.map_error(|(status, _)| (status, ())));
+        Outcome::Success(ReloadSocketGuards { requested, client })
+    }
+}
+
+// Request guard rejecting WebSocket handshakes from other sites. Browsers don't apply CORS to
+// WebSockets, so without it any open page could connect and watch the project's file paths go by.
+// The reload script's own origin, localhost on any port and `allowed_origins` pass, as do clients
+// that send no Origin at all, which browsers always do. `cors_origins` doesn't, since `["*"]` there
+// would let every site in.
+#[cfg(debug_assertions)]
+struct AllowedOrigin;
+
+#[cfg(debug_assertions)]
+impl AllowedOrigin {
+    // `scheme://host[:port]` of an Origin header, None for "null" and other opaque origins
+    fn authority(origin: &str) -> Option<&str> {
+        origin.split_once("://").map(|(_, rest)| rest.split('/').next().unwrap_or(rest)).filter(|authority| !authority.is_empty())
+    }
 
-            // Walk the directory recursively
-            Self::walk_directory(dir, &mut latest_mod_time, &mut changed_file);
+    fn is_localhost(authority: &str) -> bool {
+        let host = match authority.strip_prefix('[') {
+            Some(ipv6) => ipv6.split(']').next().unwrap_or(ipv6),
+            None => authority.split(':').next().unwrap_or(authority),
+        };
+        host.eq_ignore_ascii_case("localhost") || host.to_ascii_lowercase().ends_with(".localhost") || host.parse::<std::net::IpAddr>().is_ok_and(|ip| ip.is_loopback())
+    }
+
+    fn is_allowed(origin: &str, host: Option<&str>, config: &VigilConfig) -> bool {
+        let origin = origin.trim_end_matches('/');
+        if config.allowed_origins.iter().any(|allowed| allowed.trim_end_matches('/').eq_ignore_ascii_case(origin)) {
+            return true;
         }
 
-        // Check if we have a new modification time that is greater than the last one we saw
-        let last_time = LAST_MOD_TIME.load(Ordering::SeqCst);
+        let Some(authority) = Self::authority(origin) else {
+            return false;
+        };
+        Self::is_localhost(authority) || host.is_some_and(|host| host.eq_ignore_ascii_case(authority))
+    }
+}
 
-        if latest_mod_time > last_time {
-            // Update the atomic last mod time
-            LAST_MOD_TIME.store(latest_mod_time, Ordering::SeqCst);
-
-            // Print debug message
-            // Determine file type from extension for more helpful logging
-            let file_type = if let Some(file_path) = &changed_file {
-                if file_path.ends_with(".tera") || file_path.ends_with(".html") {
-                    "Template"
-                } else if file_path.ends_with(".css") || file_path.ends_with(".scss") {
-                    "Stylesheet"
-                } else if file_path.ends_with(".js") || file_path.ends_with(".ts") {
-                    "Script"
-                } else {
-                    "File"
-                }
-            } else {
-                "File"
-            };
+#[cfg(debug_assertions)]
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for AllowedOrigin {
+    type Error = ();
 
-            cata_log!(Debug, format!("{} change detected: {:?} at time {}", file_type, changed_file, latest_mod_time));
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let Some(origin) = request.headers().get_one("Origin") else {
+            return Outcome::Success(AllowedOrigin);
+        };
 
-            // Return the changed file path
-            changed_file
+        if Self::is_allowed(origin, request.headers().get_one("Host"), &VigilSpark::current_config()) {
+            Outcome::Success(AllowedOrigin)
         } else {
-            None
+            cata_log!(Warning, format!("Rejected reload WebSocket from origin {}, add it to allowed_origins if it is yours", origin));
+            Outcome::Error((Status::Forbidden, ()))
         }
     }
+}
 
-    // Helper function to recursively walk directories
-    fn walk_directory(dir: &Path, latest_mod_time: &mut u64, changed_file: &mut Option<String>) {
-        if let Ok(entries) = fs::read_dir(dir) {
-            for entry in entries.flatten() {
-                let path = entry.path();
+// Upgrade response confirming the negotiated sub-protocol, browsers drop connections that asked
+// for one and didn't get it back
+#[cfg(debug_assertions)]
+struct NegotiatedUpgrade<R> {
+    upgrade: R,
+    protocol: Option<&'static str>,
+}
 
-                if path.is_dir() {
-                    // Recursively walk subdirectories
-                    Self::walk_directory(&path, latest_mod_time, changed_file);
-                } else if path.is_file() {
-                    // Check for extension to determine if we should watch this file
-                    if let Some(ext) = path.extension() {
-                        let ext_str = ext.to_string_lossy().to_lowercase();
-                        // Watch templates, stylesheets, and JavaScript files
-                        if ext_str == "tera" || ext_str == "html" || ext_str == "css" || ext_str == "scss" || ext_str == "js" || ext_str == "ts" {
-                            // Get file metadata and modification time
-                            if let Ok(metadata) = fs::metadata(&path) {
-                                if let Ok(mod_time) = metadata.modified() {
-                                    if let Ok(seconds) = mod_time.duration_since(UNIX_EPOCH) {
-                                        let seconds = seconds.as_secs();
-
-                                        // Update latest mod time if newer
-                                        if seconds > *latest_mod_time {
-                                            *latest_mod_time = seconds;
-                                            *changed_file = Some(path.to_string_lossy().to_string());
-                                        }
-                                    }
-                                }
-                            }
-                        }
-                    }
-                }
-            }
+#[cfg(debug_assertions)]
+impl<'r, 'o: 'r, R: rocket::response::Responder<'r, 'o>> rocket::response::Responder<'r, 'o> for NegotiatedUpgrade<R> {
+    fn respond_to(self, request: &'r Request<'_>) -> rocket::response::Result<'o> {
+        let mut response = self.upgrade.respond_to(request)?;
+        if let Some(protocol) = self.protocol {
+            response.set_raw_header("Sec-WebSocket-Protocol", protocol);
         }
+        Ok(response)
     }
 }
 
 // WebSocket endpoint for template reloading
-#[get("/ws/dev/reload")]
-fn template_reload_websocket(ws: WebSocket) -> rocket_ws::Stream!['static] {
-    // Set the initial timestamp to now instead of 0 to avoid fake changes
-    let current_time = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
-    LAST_MOD_TIME.store(current_time, Ordering::SeqCst);
+#[cfg(debug_assertions)]
+#[get("/ws/dev/reload?<protocol>&<since>")]
+fn template_reload_websocket(guards: ReloadSocketGuards, ws: WebSocket, protocol: Option<u32>, since: Option<u64>, mut shutdown: Shutdown) -> NegotiatedUpgrade<rocket_ws::Stream!['static]> {
+    let ReloadSocketGuards { requested, client } = guards;
+    // `vigil-v1` implies protocol 1. Clients that ask for neither get the original string messages.
+    let negotiated = requested.0.iter().any(|protocol| protocol == WEBSOCKET_PROTOCOL).then_some(WEBSOCKET_PROTOCOL);
+    let protocol = if negotiated.is_some() { PROTOCOL_VERSION } else { protocol.unwrap_or(0) };
 
-    // Generate a unique connection ID
-    let connection_id = rand::random::<u32>();
-    cata_log!(Info, format!("WebSocket connection established [id={}]", connection_id));
+    // Every log line about this connection carries its id, which the client also gets in `connected`
+    let connection_id = NEXT_CONNECTION_ID.fetch_add(1, Ordering::SeqCst);
+
+    // Only unknown sub-protocols were requested, e.g. a newer client talking to an older server
+    let unsupported = (!requested.0.is_empty() && negotiated.is_none()).then(|| requested.0.join(", "));
+    if let Some(unsupported) = &unsupported {
+        cata_log!(Warning, format!("[id={}] Rejecting WebSocket connection asking for unsupported protocol {}, expected {}", connection_id, unsupported, WEBSOCKET_PROTOCOL));
+    }
+
+    let max_connections = VigilSpark::current_config().max_ws_connections;
+    let slot = ConnectionSlot::acquire(max_connections);
+    if slot.is_none() {
+        cata_log!(Warning, format!("[id={}] Closing WebSocket connection, max_ws_connections = {} reached", connection_id, max_connections));
+    }
+
+    let mut changes = ChangeDetector::subscribe(shutdown.clone());
+    // Protocol-level pings on a fixed schedule keep idle proxies from dropping the connection,
+    // whatever else is being sent
+    let mut keepalive_period = VigilSpark::keepalive_period(&VigilSpark::current_config());
+    let mut keepalive = rocket::tokio::time::interval_at(rocket::tokio::time::Instant::now() + keepalive_period, keepalive_period);
+    // When the oldest ping still waiting for its pong was sent
+    let mut unanswered_ping_at: Option<std::time::Instant> = None;
 
     // Create a stream of messages for the client
-    rocket_ws::Stream! { ws =>
+    let upgrade = { rocket_ws::Stream! { ws =>
+        let mut incoming = ws;
+
+        if let Some(unsupported) = unsupported {
+            let reason = format!("unsupported protocol {}, this server speaks {}", unsupported, WEBSOCKET_PROTOCOL);
+            yield Message::text(VigilMessage::Error { message: format!("Vigil: {}", reason) }.to_text(PROTOCOL_VERSION));
+            yield Message::Close(Some(CloseFrame { code: CloseCode::Protocol, reason: reason.into() }));
+            return;
+        }
+
+        // Held until the stream is dropped
+        let Some(_slot) = slot else {
+            yield Message::text(VigilMessage::RateLimited { limit: max_connections }.to_text(protocol));
+            yield Message::Close(Some(CloseFrame { code: CloseCode::Again, reason: "too many Vigil connections".into() }));
+            return;
+        };
+        let registration = ClientRegistration::register(connection_id, client);
+
         // Send initial connection message
-        yield Message::text(format!("connected:{}", connection_id));
+        yield Message::text(VigilMessage::Connected { id: connection_id, seq: VigilMessage::last_sequence(), boot: VigilMessage::boot_id() }.to_text(protocol));
 
-        // Add a short delay before starting to avoid initial duplicates
-        rocket::tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+        // Changes this connection asked for, everything until it subscribes. Clients send their
+        // subscription right after connecting, a short wait lets it apply to replayed changes too.
+        let mut filters: Vec<glob::Pattern> = Vec::new();
+        if let Ok(Some(Ok(Message::Text(text)))) = rocket::tokio::time::timeout(std::time::Duration::from_millis(200), incoming.next()).await {
+            match ClientMessage::parse(connection_id, &text) {
+                Some(ClientMessage::Subscribe { paths }) => filters = ClientMessage::subscription(connection_id, &paths),
+                Some(ClientMessage::Healthcheck) => {
+                    yield Message::text(ClientMessage::healthcheck(connection_id).to_text(protocol));
+                }
+                None => {}
+            }
+        }
 
-        // Define last check time to throttle file checks (every 2 seconds)
-        let mut last_check_time = Instant::now();
-        let check_interval = std::time::Duration::from_secs(2);
+        // Catch up on reloads sent while the client was disconnected
+        if let Some(since) = since {
+            for message in VigilMessage::replay_since(since).into_iter().filter_map(|message| message.filtered(&filters)) {
+                yield Message::text(message.to_text(protocol));
+                if let VigilMessage::Reload { seq, .. } = message {
+                    registration.record_sequence(seq);
+                }
+            }
+        }
 
         // Send initial timestamp
         let current_timestamp = LAST_MOD_TIME.load(Ordering::SeqCst);
-        yield Message::text(format!("time:{}", current_timestamp));
+        yield Message::text(VigilMessage::Ping { timestamp: current_timestamp }.to_text(protocol));
 
         // Main message processing loop
         loop {
-            // Check for file changes (but not too frequently)
-            let now = Instant::now();
-            if now.duration_since(last_check_time) >= check_interval {
-                last_check_time = now;
-
-                // Perform file check in a background task
-                let check_result = rocket::tokio::task::spawn_blocking(move || {
-                    VigilSpark::check_template_changes()
-                });
-
-                let result = rocket::tokio::time::timeout(
-                    std::time::Duration::from_secs(5),
-                    check_result
-                ).await;
+            let event = rocket::tokio::select! {
+                message = rocket::tokio::time::timeout(std::time::Duration::from_millis(HEARTBEAT_INTERVAL_MS), changes.recv()) => message.map_or(ConnectionEvent::Heartbeat, ConnectionEvent::Broadcast),
+                _ = keepalive.tick() => ConnectionEvent::Keepalive,
+                // Pongs prove the client is still there, a close frame or error ends the connection
+                frame = incoming.next() => match frame {
+                    Some(Ok(Message::Pong(_))) => {
+                        unanswered_ping_at = None;
+                        continue;
+                    }
+                    Some(Ok(Message::Text(text))) => match ClientMessage::parse(connection_id, &text) {
+                        Some(message) => ConnectionEvent::Client(message),
+                        None => continue,
+                    },
+                    Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                    Some(Ok(_)) => continue,
+                },
+                _ = &mut shutdown => ConnectionEvent::Broadcast(Ok(VigilMessage::Shutdown)),
+            };
 
+            match event {
+                ConnectionEvent::Client(ClientMessage::Subscribe { paths }) => {
+                    filters = ClientMessage::subscription(connection_id, &paths);
+                }
+                ConnectionEvent::Client(ClientMessage::Healthcheck) => {
+                    yield Message::text(ClientMessage::healthcheck(connection_id).to_text(protocol));
+                }
+                // Close properly so the client knows the server went away on purpose
+                ConnectionEvent::Broadcast(Ok(VigilMessage::Shutdown)) => {
+                    yield Message::text(VigilMessage::Shutdown.to_text(protocol));
+                    yield Message::Close(Some(CloseFrame { code: CloseCode::Away, reason: "server shutting down".into() }));
+                    break;
+                }
                 // If we detected a file change, send a reload command
-                if let Ok(Ok(Some(changed_file))) = result {
-                    // Determine file type for more informative logging
-                    let file_type = if changed_file.ends_with(".tera") || changed_file.ends_with(".html") {
-                        "Template"
-                    } else if changed_file.ends_with(".css") || changed_file.ends_with(".scss") {
-                        "Stylesheet"
-                    } else if changed_file.ends_with(".js") || changed_file.ends_with(".ts") {
-                        "Script"
-                    } else {
-                        "File"
-                    };
-
-                    cata_log!(Info, format!("[id={}] {} changed: {}, sending reload signal",
-                        connection_id, file_type, changed_file));
+                ConnectionEvent::Broadcast(Ok(message)) => {
+                    if let Some(message) = message.filtered(&filters) {
+                        yield Message::text(message.to_text(protocol));
+                        if let VigilMessage::Reload { seq, .. } = message {
+                            registration.record_sequence(seq);
+                        }
+                    }
+                }
+                // Skipped reloads are lost for this connection, so ask the client for one full reload
+                ConnectionEvent::Broadcast(Err(broadcast::error::RecvError::Lagged(skipped))) => {
+                    cata_log!(Warning, format!("[id={}] Client fell behind, skipped {} messages, sending resync", connection_id, skipped));
+                    yield Message::text(VigilMessage::Resync.to_text(protocol));
+                }
+                // Nothing will be sent anymore, say why instead of dropping the connection
+                ConnectionEvent::Broadcast(Err(broadcast::error::RecvError::Closed)) => {
+                    yield Message::text(VigilMessage::Error { message: String::from("Vigil change detector stopped, reload the page once the server is fixed") }.to_text(protocol));
+                    yield Message::Close(Some(CloseFrame { code: CloseCode::Error, reason: "change detector stopped".into() }));
+                    break;
+                }
+                // Otherwise send current timestamp so the client knows the server is alive
+                ConnectionEvent::Heartbeat => {
+                    let current_timestamp = LAST_MOD_TIME.load(Ordering::SeqCst);
+                    yield Message::text(VigilMessage::Ping { timestamp: current_timestamp }.to_text(protocol));
+                }
+                ConnectionEvent::Keepalive => {
+                    if unanswered_ping_at.is_some_and(|sent_at| sent_at.elapsed() >= keepalive_period * 2) {
+                        cata_log!(Warning, format!("[id={}] No pong for {}ms, closing connection", connection_id, (keepalive_period * 2).as_millis()));
+                        yield Message::Close(Some(CloseFrame { code: CloseCode::Policy, reason: "no pong received".into() }));
+                        break;
+                    }
+                    unanswered_ping_at.get_or_insert_with(std::time::Instant::now);
+                    yield Message::Ping(Vec::new());
 
-                    // Send reload command
-                    yield Message::text(format!("reload:{}", changed_file));
+                    // Edits to Catalyst.toml apply to open connections from the next ping on
+                    let configured_period = VigilSpark::keepalive_period(&VigilSpark::current_config());
+                    if configured_period != keepalive_period {
+                        keepalive_period = configured_period;
+                        keepalive = rocket::tokio::time::interval_at(rocket::tokio::time::Instant::now() + keepalive_period, keepalive_period);
+                    }
                 }
             }
+        }
+    } };
+
+    NegotiatedUpgrade { upgrade, protocol: negotiated }
+}
+
+// Server-Sent Events endpoint for environments that block WebSocket upgrades
+#[cfg(debug_assertions)]
+#[get("/vigil/events?<protocol>")]
+fn template_reload_events(_client: DevClientGuard, _token: DevToken, protocol: Option<u32>, mut shutdown: Shutdown) -> EventStream![] {
+    let protocol = protocol.unwrap_or(0);
+    // Generate a unique connection ID
+    let connection_id = NEXT_CONNECTION_ID.fetch_add(1, Ordering::SeqCst);
+    cata_log!(Info, format!("[id={}] SSE connection established", connection_id));
 
-            // Send current timestamp to client
-            let current_timestamp = LAST_MOD_TIME.load(Ordering::SeqCst);
-            yield Message::text(format!("time:{}", current_timestamp));
-            
-            // Small sleep to prevent CPU spinning
-            rocket::tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    let mut changes = ChangeDetector::subscribe(shutdown.clone());
+
+    EventStream! {
+        // Send initial connection message
+        yield Event::data(VigilMessage::Connected { id: connection_id, seq: VigilMessage::last_sequence(), boot: VigilMessage::boot_id() }.to_text(protocol));
+
+        // Idle periods are covered by the stream's own heartbeat
+        loop {
+            let next_message = rocket::tokio::select! {
+                message = changes.recv() => message,
+                _ = &mut shutdown => Ok(VigilMessage::Shutdown),
+            };
+
+            match next_message {
+                Ok(VigilMessage::Shutdown) => {
+                    yield Event::data(VigilMessage::Shutdown.to_text(protocol));
+                    break;
+                }
+                Ok(message) => {
+                    yield Event::data(message.to_text(protocol));
+                }
+                // Skipped reloads are lost for this connection, so ask the client for one full reload
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    cata_log!(Warning, format!("[id={}] Client fell behind, skipped {} messages, sending resync", connection_id, skipped));
+                    yield Event::data(VigilMessage::Resync.to_text(protocol));
+                }
+                Err(broadcast::error::RecvError::Closed) => break,
+            }
         }
     }
 }
 
+// Long-polling endpoint for proxies that buffer streaming responses, so neither WebSockets nor SSE
+// get through. Answers as soon as there is a reload newer than `since`, or after `long_poll_timeout`.
+#[cfg(debug_assertions)]
+#[get("/vigil/poll?<since>")]
+async fn long_poll(_client: DevClientGuard, _token: DevToken, since: Option<u64>, shutdown: Shutdown) -> (ContentType, String) {
+    // Subscribe before looking at the replay buffer, so a reload sent in between isn't missed
+    let mut changes = ChangeDetector::subscribe(shutdown.clone());
+    let since = since.unwrap_or_else(VigilMessage::last_sequence);
+    let mut messages = VigilMessage::replay_since(since);
+
+    if messages.is_empty() {
+        let next_messages = async {
+            loop {
+                match changes.recv().await {
+                    Ok(message @ (VigilMessage::Reload { .. } | VigilMessage::Error { .. } | VigilMessage::Shutdown)) => return vec![message],
+                    Ok(_) => continue,
+                    // The replay buffer still has whatever was skipped
+                    Err(broadcast::error::RecvError::Lagged(_)) => return VigilMessage::replay_since(since),
+                    Err(broadcast::error::RecvError::Closed) => return Vec::new(),
+                }
+            }
+        };
+
+        let timeout = std::time::Duration::from_millis(VigilSpark::current_config().long_poll_timeout as u64);
+        messages = rocket::tokio::select! {
+            result = rocket::tokio::time::timeout(timeout, next_messages) => result.unwrap_or_default(),
+            _ = shutdown => vec![VigilMessage::Shutdown],
+        };
+    }
+
+    (ContentType::JSON, serde_json::json!({ "messages": messages, "latest_seq": VigilMessage::last_sequence(), "boot": VigilMessage::boot_id() }).to_string())
+}
+
 // Endpoint to serve the JavaScript for hot reloading
+#[cfg(debug_assertions)]
 #[get("/vigil/dev-reload.js")]
-fn serve_dev_reload_js() -> RawJavaScript<&'static str> {
-    RawJavaScript(DEV_RELOAD_JS)
+fn serve_dev_reload_js(_client: DevClientGuard) -> RawJavaScript<String> {
+    let config = VigilSpark::current_config();
+    // The URLs end up inside single-quoted string literals
+    let js_string = |value: &str| value.replace('\\', "\\\\").replace('\'', "\\'");
+
+    RawJavaScript(
+        DEV_RELOAD_JS
+            .replace("__VIGIL_SSE_FALLBACK_RETRIES__", &config.sse_fallback_retries.to_string())
+            .replace("__VIGIL_PUBLIC_WS_URL__", &js_string(&config.public_ws_url))
+            .replace("__VIGIL_ROUTE_PREFIX__", &js_string(&config.route_prefix)),
+    )
 }
 
 // Endpoint to serve the script injector
+#[cfg(debug_assertions)]
 #[get("/vigil/injector.js")]
-fn serve_injector_js() -> RawJavaScript<&'static str> {
-    RawJavaScript(SCRIPT_INJECTOR_JS)
+fn serve_injector_js(_client: DevClientGuard) -> RawJavaScript<String> {
+    RawJavaScript(SCRIPT_INJECTOR_JS.replace(DEV_RELOAD_SCRIPT_PATH, &VigilSpark::dev_reload_script_url(&VigilSpark::current_config())))
 }
 
 // Endpoint to serve an HTML script tag with the script
+#[cfg(debug_assertions)]
 #[get("/vigil/inject.js")]
-fn serve_inject_script() -> RawJavaScript<String> {
+fn serve_inject_script(_client: DevClientGuard) -> RawJavaScript<String> {
     let script = r#"
     // Vigil Hot Reload Injector
     (function() {
@@ -422,12 +3486,16 @@ fn serve_inject_script() -> RawJavaScript<String> {
                 // Check if Vigil is active from the headers
                 const isActive = response.headers.get('X-Vigil-Active') === 'true';
                 const scriptPath = response.headers.get('X-Vigil-Script-Path');
+                const token = response.headers.get('X-Vigil-Token');
 
                 if (isActive && scriptPath) {
                     console.log('[Vigil] Detected via header, loading from ' + scriptPath);
                     const script = document.createElement('script');
                     script.src = scriptPath;
                     script.setAttribute('data-hotreload', 'true');
+                    if (token) {
+                        script.setAttribute('data-vigil-token', token);
+                    }
                     document.head.appendChild(script);
                 } else {
                     console.log('[Vigil] Not active or in production mode');
@@ -440,18 +3508,389 @@ fn serve_inject_script() -> RawJavaScript<String> {
     })();
     "#;
 
-    RawJavaScript(script.to_string())
+    RawJavaScript(script.to_string())
+}
+
+// Network address and prefix length of an "address" or "address/prefix" entry in `allowed_ips`
+fn parse_ip_range(entry: &str) -> Option<(std::net::IpAddr, u32)> {
+    let (address, prefix) = entry.trim().split_once('/').map_or((entry.trim(), None), |(address, prefix)| (address, Some(prefix)));
+    let address: std::net::IpAddr = address.parse().ok()?;
+    let max_prefix = if address.is_ipv4() { 32 } else { 128 };
+    let prefix = match prefix {
+        Some(prefix) => prefix.parse::<u32>().ok().filter(|prefix| *prefix <= max_prefix)?,
+        None => max_prefix,
+    };
+    Some((address, prefix))
+}
+
+// Request guard on every Vigil route. Answers 404 to other machines, e.g. on the office network
+// while the server is bound to 0.0.0.0, unless `allow_remote` is set or their address is in
+// `allowed_ips`. Rejections are logged at debug level since scanners would flood the log otherwise.
+#[cfg(debug_assertions)]
+struct DevClientGuard;
+
+#[cfg(debug_assertions)]
+impl DevClientGuard {
+    fn range_contains(entry: &str, ip: std::net::IpAddr) -> bool {
+        let Some((network, prefix)) = parse_ip_range(entry) else {
+            return false;
+        };
+        match (network, ip) {
+            (std::net::IpAddr::V4(network), std::net::IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - prefix).unwrap_or(0);
+                u32::from(network) & mask == u32::from(ip) & mask
+            }
+            (std::net::IpAddr::V6(network), std::net::IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - prefix).unwrap_or(0);
+                u128::from(network) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+
+    // Address of the client, from the first X-Forwarded-For entry when the proxy is trusted. The
+    // socket address is used otherwise, since anyone can send the headers Rocket's client_ip() reads.
+    fn client_address(request: &Request<'_>, config: &VigilConfig) -> Option<std::net::IpAddr> {
+        if config.trust_proxy_headers {
+            let forwarded = request.headers().get_one("X-Forwarded-For").and_then(|value| value.split(',').next()).and_then(|address| address.trim().parse().ok());
+            return forwarded.or_else(|| request.client_ip());
+        }
+        request.remote().map(|remote| remote.ip())
+    }
+
+    fn allows(request: &Request<'_>) -> bool {
+        let config = VigilSpark::current_config();
+        if config.allow_remote {
+            return true;
+        }
+
+        // IPv4 clients of a dual-stack listener show up as ::ffff:a.b.c.d
+        let Some(ip) = Self::client_address(request, &config).map(|ip| ip.to_canonical()) else {
+            return false;
+        };
+        ip.is_loopback() || config.allowed_ips.iter().any(|entry| Self::range_contains(entry, ip))
+    }
+}
+
+#[cfg(debug_assertions)]
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for DevClientGuard {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        if Self::allows(request) {
+            Outcome::Success(DevClientGuard)
+        } else {
+            cata_log!(Debug, format!("Hiding {} from {}, set allow_remote or allowed_ips to serve it", request.uri().path(), request.remote().map(|remote| remote.to_string()).unwrap_or_default()));
+            Outcome::Error((Status::NotFound, ()))
+        }
+    }
+}
+
+// Request guard for the reload connections and JSON endpoints. Passes every request unless
+// `dev_token` is set, then requires it as `?token=` (what the reload script sends, since browsers
+// can't add headers to WebSocket and EventSource requests) or an `X-Vigil-Token` header.
+#[cfg(debug_assertions)]
+struct DevToken;
+
+#[cfg(debug_assertions)]
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for DevToken {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let Some(expected) = VigilSpark::current_config().dev_token else {
+            return Outcome::Success(DevToken);
+        };
+
+        let provided = request.headers().get_one("X-Vigil-Token").or_else(|| request.query_value::<&str>("token").and_then(Result::ok)).unwrap_or("");
+        if bool::from(provided.as_bytes().ct_eq(expected.expose().as_bytes())) {
+            return Outcome::Success(DevToken);
+        }
+
+        // A page left open with an old token retries every few seconds, so count rejections and log
+        // them at most every TOKEN_REJECTION_LOG_MS
+        let rejections = TOKEN_REJECTIONS.fetch_add(1, Ordering::SeqCst) + 1;
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as u64;
+        let logged_at = TOKEN_REJECTION_LOGGED_AT.load(Ordering::SeqCst);
+        if now.saturating_sub(logged_at) >= TOKEN_REJECTION_LOG_MS && TOKEN_REJECTION_LOGGED_AT.compare_exchange(logged_at, now, Ordering::SeqCst, Ordering::SeqCst).is_ok() {
+            TOKEN_REJECTIONS.store(0, Ordering::SeqCst);
+            cata_log!(Warning, format!("Rejected {} request(s) with a missing or wrong dev_token, latest to {}", rejections, request.uri().path()));
+        }
+        Outcome::Error((Status::Unauthorized, ()))
+    }
+}
+
+// Request guard for the status and API routes. Passes every request unless both
+// `vigil_api_username` and `vigil_api_password` are set, then requires matching Basic Auth.
+#[cfg(debug_assertions)]
+struct VigilAuth;
+
+#[cfg(debug_assertions)]
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for VigilAuth {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let config = VigilSpark::current_config();
+        let (Some(username), Some(password)) = (config.vigil_api_username, config.vigil_api_password) else {
+            return Outcome::Success(VigilAuth);
+        };
+        let password = password.expose();
+
+        // Compare encoded credentials instead of decoding the header, in constant time so the
+        // response time doesn't reveal how much of the password was right
+        let expected = base64::engine::general_purpose::STANDARD.encode(format!("{}:{}", username, password));
+        let provided = request.headers().get_one("Authorization").and_then(|value| value.strip_prefix("Basic ")).map(str::trim).unwrap_or("");
+
+        if bool::from(provided.as_bytes().ct_eq(expected.as_bytes())) {
+            Outcome::Success(VigilAuth)
+        } else {
+            cata_log!(Warning, format!("Rejected unauthenticated request to {}", request.uri().path()));
+            request.local_cache(|| BasicAuthRejected(true));
+            Outcome::Error((Status::Unauthorized, ()))
+        }
+    }
+}
+
+// Set in the request cache by VigilAuth, so the catcher can tell its rejections from DevToken's
+#[cfg(debug_assertions)]
+struct BasicAuthRejected(bool);
+
+// Rejections by VigilAuth carry a challenge so browsers show a login prompt. A missing dev_token
+// doesn't, since no username and password would help.
+#[cfg(debug_assertions)]
+#[derive(Responder)]
+enum VigilUnauthorized {
+    #[response(status = 401, content_type = "plain")]
+    Credentials(&'static str, Header<'static>),
+    #[response(status = 401, content_type = "plain")]
+    Token(&'static str),
+}
+
+#[cfg(debug_assertions)]
+#[catch(401)]
+fn vigil_unauthorized(request: &Request) -> VigilUnauthorized {
+    if request.local_cache(|| BasicAuthRejected(false)).0 {
+        VigilUnauthorized::Credentials("Vigil credentials required", Header::new("WWW-Authenticate", r#"Basic realm="Vigil", charset="UTF-8""#))
+    } else {
+        VigilUnauthorized::Token("Vigil dev_token required")
+    }
+}
+
+// Diagnostic endpoint returning the configuration actually in use
+#[cfg(debug_assertions)]
+#[get("/vigil/api/config")]
+fn serve_config(_client: DevClientGuard, _token: DevToken, _auth: VigilAuth) -> (ContentType, String) {
+    let instance = VigilSpark {
+        environment: VigilSpark::global_environment(),
+        config: VigilSpark::current_config(),
+    };
+
+    let mut value = serde_json::to_value(&instance).unwrap_or_default();
+    if let Some(object) = value.as_object_mut() {
+        // Effective directories after merging extra_watch_dirs and Rust sources
+        object.insert("watched_dirs".to_string(), serde_json::json!(VigilSpark::watch_dirs(&instance.config)));
+
+        // Milliseconds since the epoch, plus a readable form for humans
+        let last_mod_time = LAST_MOD_TIME.load(Ordering::SeqCst);
+        object.insert("last_mod_time".to_string(), serde_json::json!(last_mod_time));
+        object.insert("last_mod_time_utc".to_string(), serde_json::json!(format_timestamp(last_mod_time)));
+
+        // Spelled out so a dry run isn't mistaken for a broken watcher
+        object.insert("mode".to_string(), serde_json::json!(if instance.config.dry_run { "dry-run" } else { "live" }));
+    }
+
+    (ContentType::JSON, value.to_string())
+}
+
+// Every file change detection currently covers, to find out why a file doesn't reload
+#[cfg(debug_assertions)]
+#[get("/vigil/api/watched-files")]
+async fn serve_watched_files(_client: DevClientGuard, _token: DevToken, _auth: VigilAuth) -> (ContentType, String) {
+    let (scanned_now, watched_files) = rocket::tokio::task::spawn_blocking(ChangeDetector::watched_files).await.unwrap_or_default();
+
+    let mut files: Vec<WatchedFileInfo> = watched_files
+        .iter()
+        .map(|(path, stamp)| WatchedFileInfo {
+            path: path.to_string_lossy().to_string(),
+            last_mtime: stamp.mod_time,
+            extension: path.extension().map(|extension| extension.to_string_lossy().to_string()).unwrap_or_default(),
+        })
+        .collect();
+    files.sort_by(|a, b| a.path.cmp(&b.path));
+    files.truncate(WATCHED_FILES_LIMIT);
+
+    let listing = serde_json::json!({
+        "source": if scanned_now { "scan" } else { "last-poll" },
+        "total": watched_files.len(),
+        "truncated": watched_files.len() > WATCHED_FILES_LIMIT,
+        "files": files,
+    });
+
+    (ContentType::JSON, listing.to_string())
+}
+
+// Optional body of a manual reload request
+#[cfg(debug_assertions)]
+#[derive(Deserialize)]
+struct ManualReload {
+    file: Option<PathBuf>,
+    // Several files written by one build step, sent as a single batch
+    #[serde(default)]
+    files: Vec<PathBuf>,
+}
+
+// Endpoint for build tools to reload every connected browser without touching a watched file
+#[cfg(debug_assertions)]
+#[post("/vigil/api/reload", data = "<body>")]
+fn trigger_reload(_client: DevClientGuard, _token: DevToken, _auth: VigilAuth, body: String) -> (ContentType, String) {
+    // An empty or unparsable body is still a valid reload request
+    let mut files = serde_json::from_str::<ManualReload>(&body).map(|request| request.file.into_iter().chain(request.files).collect::<Vec<_>>()).unwrap_or_default();
+    files.sort_unstable();
+    files.dedup();
+
+    // All files go out in one message, so browsers reload once however many a build step wrote
+    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as u64;
+    let changes = DetectedChanges { modified: files, ..Default::default() };
+    let message = VigilMessage::reload("reload", changes.events(timestamp));
+    METRICS.record_reload();
+    message.remember();
+
+    // The channel only exists once a client has connected
+    let clients = CHANGE_BROADCAST.get().and_then(|sender| sender.send(message.clone()).ok()).unwrap_or(0);
+    cata_log!(Info, format!("Manual reload requested, sent {} to {} clients", message.to_text(PROTOCOL_VERSION), clients));
+
+    (ContentType::JSON, serde_json::json!({ "message": message, "clients": clients }).to_string())
+}
+
+// Connected WebSocket clients keyed by the connection id their browser console shows
+#[cfg(debug_assertions)]
+#[get("/vigil/api/clients")]
+fn serve_clients(_client: DevClientGuard, _token: DevToken, _auth: VigilAuth) -> (ContentType, String) {
+    let clients: serde_json::Map<String, serde_json::Value> = ConnectedClient::all().into_iter().map(|(id, client)| (id.to_string(), serde_json::to_value(client).unwrap_or_default())).collect();
+    (ContentType::JSON, serde_json::Value::Object(clients).to_string())
+}
+
+// Reload and scan counters as JSON
+#[cfg(debug_assertions)]
+#[get("/vigil/api/metrics")]
+fn serve_metrics(_client: DevClientGuard, _token: DevToken, _auth: VigilAuth) -> (ContentType, String) {
+    (ContentType::JSON, METRICS.to_json().to_string())
+}
+
+// The same counters for a Prometheus scraper
+#[cfg(debug_assertions)]
+#[get("/vigil/metrics")]
+fn serve_prometheus_metrics(_client: DevClientGuard, _auth: VigilAuth) -> (ContentType, String) {
+    (ContentType::new("text", "plain").with_params(("version", "0.0.4")), METRICS.prometheus_text())
+}
+
+// Example of every message protocol 1 clients receive, for building other clients on top of it
+#[cfg(debug_assertions)]
+#[get("/vigil/protocol.json")]
+fn serve_protocol(_client: DevClientGuard) -> (ContentType, String) {
+    let timestamp = 1_714_566_605_123;
+    let event = ChangeEvent { path: PathBuf::from("templates/index.tera"), url: None, kind: ChangeKind::Modified, category: "Template", timestamp, previous_path: None };
+    let renamed = ChangeEvent {
+        path: PathBuf::from("public/css/site.css"),
+        url: Some(String::from("/css/site.css")),
+        kind: ChangeKind::Renamed,
+        category: "Stylesheet",
+        timestamp,
+        previous_path: Some(PathBuf::from("public/css/main.css")),
+    };
+
+    let messages = [
+        VigilMessage::Connected { id: 1, seq: timestamp, boot: "18f3a2c4d10-4d2" },
+        VigilMessage::Ping { timestamp },
+        VigilMessage::Reload { seq: timestamp + 1, action: "reload", count: 2, delay: 0, events: vec![event, renamed] },
+        VigilMessage::Resync,
+        VigilMessage::ConfigReloaded,
+        VigilMessage::RateLimited { limit: 10 },
+        VigilMessage::Error { message: String::from("Vigil found more than 20000 watched files, pausing change detection.") },
+        VigilMessage::Shutdown,
+        VigilMessage::Healthcheck { uptime_secs: 3600, total_reloads: 42, connected_clients: 2, server_time: timestamp },
+    ];
+
+    let schema = serde_json::json!({
+        "protocol": PROTOCOL_VERSION,
+        "connect": ["/ws/dev/reload?protocol=1&since=<seq>", "/vigil/events?protocol=1", "/vigil/poll?since=<seq>"],
+        "actions": ["reload", "partial", "css-swap", "asset", "i18n", "rebuild"],
+        "kinds": ["created", "modified", "deleted", "renamed"],
+        "messages": messages,
+        "client_messages": [{ "type": "subscribe", "paths": ["templates/admin/**"] }, { "type": "healthcheck" }],
+    });
+
+    (ContentType::JSON, schema.to_string())
 }
 
 // Endpoint to serve the manifest.toml
+#[cfg(debug_assertions)]
 #[get("/vigil/manifest.toml")]
-fn serve_manifest() -> (ContentType, &'static str) {
+fn serve_manifest(_client: DevClientGuard) -> (ContentType, &'static str) {
     (ContentType::Plain, MANIFEST_TOML)
 }
 
 // Debug endpoint to verify integration
+#[cfg(debug_assertions)]
 #[get("/vigil/status")]
-fn serve_status() -> (ContentType, String) {
+fn serve_status(_client: DevClientGuard, _auth: VigilAuth) -> (ContentType, String) {
+    // Newest changes first
+    let change_rows: String = CHANGE_LOG
+        .lock()
+        .map(|change_log| {
+            change_log
+                .iter()
+                .rev()
+                .map(|event| {
+                    format!(
+                        "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+                        format_timestamp(event.timestamp),
+                        escape_html(&wire_path(&event.path)),
+                        event.kind.as_str(),
+                        event.category
+                    )
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+
+    let clients = ConnectedClient::all();
+    let client_rows: String = clients
+        .iter()
+        .map(|(id, client)| {
+            format!(
+                "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+                id,
+                client.address.map(|address| address.to_string()).unwrap_or_default(),
+                escape_html(client.user_agent.as_deref().unwrap_or_default()),
+                format_timestamp(client.connected_at),
+                client.last_seq
+            )
+        })
+        .collect();
+
+    let config = VigilSpark::current_config();
+    let max_files = config.max_watched_files;
+    let watched_files = match WATCHED_FILE_COUNT.lock().ok().and_then(|count| *count) {
+        Some(count) if max_files > 0 => format!("{} of at most {}", count, max_files),
+        Some(count) => count.to_string(),
+        None => String::from("not scanned, using native file events"),
+    };
+    let watched_dirs = VigilSpark::watch_dirs(&config).iter().map(|dir| escape_html(&dir.to_string_lossy())).collect::<Vec<_>>().join(", ");
+    let mut warnings = String::new();
+    if WATCH_LIMIT_EXCEEDED.load(Ordering::SeqCst) {
+        warnings.push_str(&format!("<p><strong>Change detection paused: more than {} files are watched. Narrow watch_dirs or raise max_watched_files.</strong></p>", max_files));
+    }
+    let scan_errors = SCAN_ERRORS.load(Ordering::SeqCst);
+    if scan_errors > 0 {
+        warnings.push_str(&format!("<p><strong>{} scans failed in a row, next scan in {}ms.</strong></p>", scan_errors, SCAN_BACKOFF_MS.load(Ordering::SeqCst)));
+    }
+    if config.dry_run {
+        warnings.push_str("<p><strong>Dry run: changes are logged at debug level but browsers are not reloaded.</strong></p>");
+    }
+
     let status = format!(
         r#"
     <html>
@@ -462,22 +3901,108 @@ fn serve_status() -> (ContentType, String) {
         <h1>Vigil Development Tools</h1>
         <p>Status: Active</p>
         <p>Environment: {}</p>
+        <p>Boot ID: {}</p>
         <p>Hot Reload: Enabled</p>
         <p>Last check: {}</p>
+        <p>Watched files: {}</p>
+        <p>Watched directories: {}</p>
+        <p>WebSocket connections: {}</p>
+        <p>Keepalive interval: {}ms</p>
+        {}
         <p>This page should have the auto-reload script injected.</p>
+        <h2>Connected Clients ({})</h2>
+        <table>
+            <tr><th>Id</th><th>Address</th><th>User agent</th><th>Connected</th><th>Last reload sent</th></tr>
+            {}
+        </table>
+        <h2>Recent Changes</h2>
+        <table>
+            <tr><th>Timestamp</th><th>File</th><th>Change</th><th>Type</th></tr>
+            {}
+        </table>
     </body>
     </html>
     "#,
-        VIGIL_INSTANCE.get().map(|i| &i.environment).unwrap_or(&String::from("unknown")),
-        LAST_MOD_TIME.load(Ordering::SeqCst)
+        VigilSpark::global_environment(),
+        VigilMessage::boot_id(),
+        format_timestamp(LAST_MOD_TIME.load(Ordering::SeqCst)),
+        watched_files,
+        watched_dirs,
+        match config.max_ws_connections {
+            0 => WS_CONNECTIONS.load(Ordering::SeqCst).to_string(),
+            limit => format!("{} of at most {}", WS_CONNECTIONS.load(Ordering::SeqCst), limit),
+        },
+        config.ping_interval,
+        warnings,
+        clients.len(),
+        client_rows,
+        change_rows
     );
 
     (ContentType::HTML, status)
 }
 
+// Format milliseconds since the epoch as a UTC timestamp, e.g. "2024-05-01 12:30:05.123 UTC"
+#[cfg(debug_assertions)]
+fn format_timestamp(millis: u64) -> String {
+    let seconds = millis / 1000;
+    let (hours, minutes, secs) = ((seconds / 3600) % 24, (seconds / 60) % 60, seconds % 60);
+
+    // Convert days since the epoch to a civil date (Howard Hinnant's algorithm)
+    let days = (seconds / 86400) as i64 + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days.rem_euclid(146097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!("{:04}-{:02}-{:02} {:02}:{:02}:{:02}.{:03} UTC", year, month, day, hours, minutes, secs, millis % 1000)
+}
+
+// Escape text for inclusion in the status page
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
 // Fairing to inject our script directly into HTML responses
-struct ScriptInjectionFairing;
+#[cfg(debug_assertions)]
+struct ScriptInjectionFairing {
+    body_injection: bool,
+}
+
+#[cfg(debug_assertions)]
+impl ScriptInjectionFairing {
+    // Insert the reload script before </body> so it doesn't hold up rendering, or before </head> for
+    // pages without a closing body tag
+    async fn inject_script_tag(response: &mut Response<'_>, script_tag: &str) {
+        let mut body = match response.body_mut().to_string().await {
+            Ok(body) => body,
+            Err(e) => {
+                cata_log!(Warning, format!("Could not read response body for script injection: {}", e));
+                return;
+            }
+        };
+
+        // Pages that already load the script (e.g. through makeuse) don't need a second copy
+        if !body.contains(DEV_RELOAD_SCRIPT_PATH) {
+            // ASCII lowercasing keeps byte offsets, so positions map back onto the original body
+            let lowercase_body = body.to_ascii_lowercase();
+            match lowercase_body.rfind("</body>").or_else(|| lowercase_body.find("</head>")) {
+                Some(position) => body.insert_str(position, script_tag),
+                None => {
+                    cata_log!(Debug, "No </body> or </head> found, skipping script injection");
+                }
+            }
+        }
+
+        response.set_sized_body(body.len(), std::io::Cursor::new(body));
+    }
+}
 
+#[cfg(debug_assertions)]
 #[rocket::async_trait]
 impl Fairing for ScriptInjectionFairing {
     fn info(&self) -> Info {
@@ -487,14 +4012,27 @@ impl Fairing for ScriptInjectionFairing {
         }
     }
 
-    async fn on_response<'r>(&self, _request: &'r Request<'_>, response: &mut Response<'r>) {
-        // Only inject headers for HTML content
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        // Clients the Vigil routes are hidden from couldn't load the script anyway
+        if !DevClientGuard::allows(request) {
+            return;
+        }
+
+        // Only inject into HTML content
         if let Some(content_type) = response.content_type() {
             if content_type.is_html() {
                 // Add HTTP headers for the JS snippet to detect
                 response.set_header(Header::new("X-Vigil-Active", "true"));
                 response.set_header(Header::new("X-Vigil-HotReload", "true"));
-                response.set_header(Header::new("X-Vigil-Script-Path", "/vigil/dev-reload.js"));
+                let config = VigilSpark::current_config();
+                let script_url = VigilSpark::dev_reload_script_url(&config);
+                response.set_header(Header::new("X-Vigil-Script-Path", script_url.clone()));
+
+                // The page hands the token to the reload script, which is served without it
+                let dev_token = config.dev_token;
+                if let Some(token) = &dev_token {
+                    response.set_header(Header::new("X-Vigil-Token", token.expose().to_string()));
+                }
 
                 // Also add a CSP header to allow inline scripts
                 let existing_csp = response.headers().get_one("Content-Security-Policy");
@@ -505,20 +4043,115 @@ impl Fairing for ScriptInjectionFairing {
                     // Set a new CSP
                     response.set_header(Header::new("Content-Security-Policy", "script-src 'self' 'unsafe-inline';"));
                 }
+
+                // Insert the script tag into the body so pages don't need a second request to find it
+                if self.body_injection {
+                    let token_attribute = dev_token.map(|token| format!(r#" data-vigil-token="{}""#, escape_html(token.expose()))).unwrap_or_default();
+                    let script_tag = format!(r#"<script src="{}" data-hotreload="true"{}></script>"#, escape_html(&script_url), token_attribute);
+                    Self::inject_script_tag(response, &script_tag).await;
+                }
+            }
+        }
+    }
+}
+
+// Fairing adding CORS headers to /vigil responses for the origins in `cors_origins`, so dev tools
+// served from another port can read the API. Preflight requests have no route, so their 404 is
+// turned into an empty 204.
+#[cfg(debug_assertions)]
+struct VigilCorsFairing;
+
+#[cfg(debug_assertions)]
+impl VigilCorsFairing {
+    fn allows_origin(origin: &str, config: &VigilConfig) -> bool {
+        let origin = origin.trim_end_matches('/');
+        config.cors_origins.iter().map(|allowed| allowed.trim_end_matches('/')).any(|allowed| {
+            allowed.eq_ignore_ascii_case(origin) || glob::Pattern::new(allowed).is_ok_and(|pattern| pattern.matches_with(origin, glob::MatchOptions { case_sensitive: false, ..Default::default() }))
+        })
+    }
+}
+
+#[cfg(debug_assertions)]
+#[rocket::async_trait]
+impl Fairing for VigilCorsFairing {
+    fn info(&self) -> Info {
+        Info {
+            name: "Vigil CORS",
+            kind: Kind::Response,
+        }
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        if !request.uri().path().as_str().starts_with("/vigil/") || !DevClientGuard::allows(request) {
+            return;
+        }
+        let Some(origin) = request.headers().get_one("Origin") else {
+            return;
+        };
+
+        let config = VigilSpark::current_config();
+        if !Self::allows_origin(origin, &config) {
+            return;
+        }
+
+        // A lone "*" allows any page, anything else gets its own origin back
+        let allow_origin = if config.cors_origins.iter().any(|allowed| allowed == "*") { "*" } else { origin };
+        response.set_header(Header::new("Access-Control-Allow-Origin", allow_origin.to_string()));
+        response.set_header(Header::new("Vary", "Origin"));
+
+        if request.method() == rocket::http::Method::Options {
+            response.set_header(Header::new("Access-Control-Allow-Methods", "GET, POST, OPTIONS"));
+            response.set_header(Header::new("Access-Control-Allow-Headers", "Authorization, Content-Type, X-Vigil-Token"));
+            response.set_header(Header::new("Access-Control-Max-Age", "600"));
+            if response.status() == Status::NotFound {
+                response.set_status(Status::NoContent);
+                response.set_sized_body(0, std::io::Cursor::new(""));
             }
         }
     }
 }
 
+// Fairing removing Vigil's development headers outside the dev environment, in case a proxy
+// cached a dev response and replays it. Attached in every environment and build.
+struct VigilCleanupFairing {
+    strip_headers: bool,
+}
+
+#[rocket::async_trait]
+impl Fairing for VigilCleanupFairing {
+    fn info(&self) -> Info {
+        Info {
+            name: "Vigil Header Cleanup",
+            kind: Kind::Response,
+        }
+    }
+
+    async fn on_response<'r>(&self, _request: &'r Request<'_>, response: &mut Response<'r>) {
+        if !self.strip_headers {
+            return;
+        }
+
+        let vigil_headers: Vec<String> = response.headers().iter().map(|header| header.name().to_string()).filter(|name| name.to_ascii_lowercase().starts_with("x-vigil-")).collect();
+        for name in vigil_headers {
+            response.remove_header(&name);
+        }
+    }
+}
+
 // Implementation of the Spark trait for the vigil module
 impl Spark for VigilSpark {
     fn initialize(&mut self) -> Result<(), Box<dyn std::error::Error>> {
         cata_log!(Info, format!("Vigil spark initialized in {} environment", self.environment));
 
-        // Register template components if in development mode
-        if self.environment == "dev" && self.config.template_hot_reload {
+        if let Some(error) = Self::critical_config_error(&self.config) {
+            cata_log!(Error, format!("Invalid Vigil configuration: {}", error));
+            return Err(error.into());
+        }
+
+        // Register template components if in development mode, unless the fairing injects the script itself
+        if cfg!(debug_assertions) && self.environment == "dev" && self.config.template_hot_reload && !self.config.body_injection {
             // Register hot reload script via the makeuse API
-            makeuse::register_head_script("vigil", r#"<script src="/vigil/inject.js"></script>"#.to_string(), true);
+            makeuse::register_head_script("vigil", format!(r#"<script src="{}/vigil/inject.js"></script>"#, escape_html(&self.config.route_prefix)), true);
 
             cata_log!(Debug, "Registered Vigil hot reload script in template components");
         }
@@ -526,21 +4159,37 @@ impl Spark for VigilSpark {
         Ok(())
     }
 
+    #[cfg(debug_assertions)]
     fn attach_to_rocket(&self, rocket: Rocket<Build>) -> Rocket<Build> {
+        let rocket = rocket.attach(VigilCleanupFairing { strip_headers: self.environment != "dev" });
+
         // Only attach template watching routes in development mode
         if self.environment == "dev" {
             cata_log!(Info, "Vigil: Development mode detected - enabling template hot reload");
 
             // These routes will be available in dev mode only
             rocket
-                .mount("/", routes![template_reload_websocket, serve_dev_reload_js, serve_injector_js, serve_inject_script, serve_manifest, serve_status])
-                .attach(ScriptInjectionFairing)
+                .mount("/", routes![template_reload_websocket, template_reload_events, long_poll, serve_dev_reload_js, serve_injector_js, serve_inject_script, serve_manifest, serve_protocol, serve_status, serve_config, serve_metrics, serve_prometheus_metrics, serve_watched_files, serve_clients, trigger_reload])
+                .register("/vigil", catchers![vigil_unauthorized])
+                .attach(ScriptInjectionFairing { body_injection: self.config.body_injection })
+                .attach(VigilCorsFairing)
         } else {
             cata_log!(Info, "Vigil: Production mode detected - template hot reload disabled");
             rocket
         }
     }
 
+    // Release builds never contain the development routes, whatever the environment says
+    #[cfg(not(debug_assertions))]
+    fn attach_to_rocket(&self, rocket: Rocket<Build>) -> Rocket<Build> {
+        if self.environment == "dev" {
+            cata_log!(Warning, "Vigil: Release build - template hot reload is not compiled in, ignoring dev environment");
+        }
+
+        // Nothing in a release build sets Vigil headers, so any that show up came from a cached dev response
+        rocket.attach(VigilCleanupFairing { strip_headers: true })
+    }
+
     fn name(&self) -> &str {
         "vigil"
     }
@@ -550,8 +4199,665 @@ impl Spark for VigilSpark {
     }
 }
 
+// Access the running Vigil instance, e.g. to include debug assets only while hot reload is on.
+// Returns None until the spark has been created or after `VigilSpark::reset`.
+pub fn vigil_config() -> Option<VigilSpark> {
+    VIGIL_INSTANCE.read().ok().and_then(|global| global.clone())
+}
+
+// Register a callback run with the changed paths whenever translation files under `locales_dir`
+// change, before the browser reloads. Use it to invalidate cached translation catalogs.
+pub fn on_translations_changed(hook: impl Fn(&[PathBuf]) + Send + 'static) {
+    if let Ok(mut hooks) = TRANSLATION_HOOKS.lock() {
+        hooks.push(Box::new(hook));
+    }
+}
+
 // Export a function to create the spark
 pub fn create_spark() -> Box<dyn crate::services::sparks::registry::Spark> {
     Box::new(VigilSpark::new())
 }
 
+
+// The scanner and configuration live in globals, so tests that use them hold TEST_LOCK and start
+// from `VigilSpark::reset`. Fixtures are real files under the system temp directory. Only debug
+// builds contain the watcher, so release test runs skip the module.
+#[cfg(all(test, debug_assertions))]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::MutexGuard;
+    use std::time::Duration;
+
+    static TEST_LOCK: Mutex<()> = Mutex::new(());
+    static NEXT_TEMP_DIR: AtomicUsize = AtomicUsize::new(0);
+
+    fn lock() -> MutexGuard<'static, ()> {
+        TEST_LOCK.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    // A new directory under the system temp directory, removed again when dropped. This stands in for
+    // tempfile: spark manifests only have [dependencies], so it would be compiled into every app.
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let path = std::env::temp_dir().join(format!("vigil-{}-{}-{}", name, std::process::id(), NEXT_TEMP_DIR.fetch_add(1, Ordering::SeqCst)));
+            let _ = fs::remove_dir_all(&path);
+            fs::create_dir_all(&path).unwrap();
+            TempDir(path)
+        }
+
+        fn path(&self) -> &Path {
+            &self.0
+        }
+
+        fn write(&self, name: &str, contents: &str) -> PathBuf {
+            let path = self.0.join(name);
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent).unwrap();
+            }
+            fs::write(&path, contents).unwrap();
+            path
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    // Watch only `dir`, with nothing picked up from the project the tests run in
+    fn scan_config(dir: &Path) -> VigilConfig {
+        VigilConfig {
+            watch_dirs: vec![WatchDirConfig::new(dir)],
+            extra_watch_dirs: Vec::new(),
+            watch_files: Vec::new(),
+            watch_rust_sources: false,
+            respect_gitignore: false,
+            parallel_scan: false,
+            locales_dir: dir.join("locales"),
+            sentinel_file: None,
+            ..VigilConfig::default()
+        }
+    }
+
+    // Make `config` the active configuration and take the baseline scan
+    fn start_scanning(config: VigilConfig) {
+        VigilSpark::reset();
+        VigilSpark::with_config(config, "dev");
+        VigilSpark::check_template_changes();
+    }
+
+    fn mod_time(path: &Path) -> SystemTime {
+        fs::metadata(path).and_then(|metadata| metadata.modified()).unwrap()
+    }
+
+    fn set_mod_time(path: &Path, time: SystemTime) {
+        fs::File::options().write(true).open(path).and_then(|file| file.set_modified(time)).unwrap();
+    }
+
+    #[test]
+    fn newer_file_is_detected() {
+        let _guard = lock();
+        let dir = TempDir::new("newer");
+        let page = dir.write("page.html", "<p>old</p>");
+        start_scanning(scan_config(dir.path()));
+
+        set_mod_time(&page, mod_time(&page) + Duration::from_secs(10));
+        assert_eq!(VigilSpark::check_template_changes().modified, vec![page]);
+        assert!(VigilSpark::check_template_changes().is_empty());
+    }
+
+    #[test]
+    fn untouched_files_are_not_reported() {
+        let _guard = lock();
+        let dir = TempDir::new("untouched");
+        dir.write("page.html", "<p>same</p>");
+        dir.write("css/site.css", "body {}");
+        start_scanning(scan_config(dir.path()));
+
+        assert!(VigilSpark::check_template_changes().is_empty());
+    }
+
+    #[test]
+    fn empty_directory_reports_nothing_until_a_file_appears() {
+        let _guard = lock();
+        let dir = TempDir::new("empty");
+        start_scanning(scan_config(dir.path()));
+
+        assert!(VigilSpark::check_template_changes().is_empty());
+        let page = dir.write("page.html", "<p>new</p>");
+        assert_eq!(VigilSpark::check_template_changes().created, vec![page]);
+    }
+
+    #[test]
+    fn missing_directory_is_watched_once_it_exists() {
+        let _guard = lock();
+        let dir = TempDir::new("missing");
+        let missing = dir.path().join("templates");
+        start_scanning(scan_config(&missing));
+
+        assert!(VigilSpark::check_template_changes().is_empty());
+        let page = dir.write("templates/page.html", "<p>new</p>");
+        assert_eq!(VigilSpark::check_template_changes().created, vec![page]);
+    }
+
+    #[test]
+    fn unrecognized_extensions_are_ignored() {
+        let _guard = lock();
+        let dir = TempDir::new("extensions");
+        let notes = dir.write("notes.txt", "old");
+        start_scanning(scan_config(dir.path()));
+
+        set_mod_time(&notes, mod_time(&notes) + Duration::from_secs(10));
+        dir.write("archive.xyz", "new");
+        assert!(VigilSpark::check_template_changes().is_empty());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlinked_directories_are_only_followed_when_enabled() {
+        let _guard = lock();
+        let dir = TempDir::new("symlink");
+        let outside = TempDir::new("symlink-target");
+        let page = outside.write("page.html", "<p>old</p>");
+        std::os::unix::fs::symlink(outside.path(), dir.path().join("linked")).unwrap();
+
+        start_scanning(scan_config(dir.path()));
+        set_mod_time(&page, mod_time(&page) + Duration::from_secs(10));
+        assert!(VigilSpark::check_template_changes().is_empty());
+
+        start_scanning(VigilConfig { follow_symlinks: true, ..scan_config(dir.path()) });
+        set_mod_time(&page, mod_time(&page) + Duration::from_secs(10));
+        assert_eq!(VigilSpark::check_template_changes().modified, vec![dir.path().join("linked/page.html")]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlink_loops_are_walked_once() {
+        let _guard = lock();
+        let dir = TempDir::new("symlink-loop");
+        let page = dir.write("page.html", "<p>old</p>");
+        std::os::unix::fs::symlink(dir.path(), dir.path().join("loop")).unwrap();
+        start_scanning(VigilConfig { follow_symlinks: true, ..scan_config(dir.path()) });
+
+        set_mod_time(&page, mod_time(&page) + Duration::from_secs(10));
+        assert_eq!(VigilSpark::check_template_changes().modified, vec![page]);
+    }
+
+    #[test]
+    fn hash_mode_detects_rewrites_that_keep_size_and_mod_time() {
+        let _guard = lock();
+        let dir = TempDir::new("hash");
+        let page = dir.write("page.html", "<p>aaa</p>");
+        let original_time = mod_time(&page);
+
+        start_scanning(scan_config(dir.path()));
+        fs::write(&page, "<p>bbb</p>").unwrap();
+        set_mod_time(&page, original_time);
+        assert!(VigilSpark::check_template_changes().is_empty());
+
+        start_scanning(VigilConfig { change_detection: ChangeDetection::Hash, ..scan_config(dir.path()) });
+        fs::write(&page, "<p>ccc</p>").unwrap();
+        set_mod_time(&page, original_time);
+        assert_eq!(VigilSpark::check_template_changes().modified, vec![page]);
+    }
+
+    #[test]
+    fn default_config_round_trips_through_toml() {
+        let _guard = lock();
+        let config = VigilConfig::default();
+        assert_eq!(VigilConfig::from_toml_str(&config.to_toml_string()).unwrap(), config);
+    }
+
+    #[test]
+    fn custom_config_round_trips_through_toml() {
+        let _guard = lock();
+        let config = VigilConfig {
+            refresh_interval: 250,
+            dry_run: true,
+            follow_symlinks: true,
+            change_detection: ChangeDetection::Hash,
+            max_depth: Some(3),
+            watch_dirs: vec![WatchDirConfig { path: PathBuf::from("web/views"), extensions: Some(vec![String::from("tera")]) }],
+            extra_watch_dirs: vec![PathBuf::from("static")],
+            sentinel_file: Some(PathBuf::from(".vigil-changed")),
+            ignore: VigilSpark::compile_patterns(&[String::from("*.bak")]),
+            cors_origins: vec![String::from("http://localhost:*")],
+            route_prefix: String::from("/app"),
+            ..VigilConfig::default()
+        };
+        assert_eq!(VigilConfig::from_toml_str(&config.to_toml_string()).unwrap(), config);
+    }
+
+    #[test]
+    fn rename_is_reported_as_a_single_change() {
+        let _guard = lock();
+        let dir = TempDir::new("rename");
+        let old_path = dir.write("header.tera", "<header></header>");
+        let new_path = dir.path().join("site_header.tera");
+        start_scanning(scan_config(dir.path()));
+
+        fs::rename(&old_path, &new_path).unwrap();
+        let changes = VigilSpark::check_template_changes();
+        assert_eq!(changes.renamed, vec![(old_path.clone(), new_path.clone())]);
+        assert!(changes.created.is_empty() && changes.deleted.is_empty() && changes.modified.is_empty());
+
+        let messages = VigilSpark::change_messages(&changes);
+        let [VigilMessage::Reload { action, events, .. }] = messages.as_slice() else {
+            panic!("expected one reload message");
+        };
+        assert_eq!(*action, "reload");
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].kind, ChangeKind::Renamed);
+        assert_eq!(events[0].path, new_path);
+        assert_eq!(events[0].previous_path.as_deref(), Some(old_path.as_path()));
+    }
+
+    #[test]
+    fn move_into_a_subdirectory_is_a_rename() {
+        let _guard = lock();
+        let dir = TempDir::new("move");
+        let old_path = dir.write("header.tera", "<header></header>");
+        fs::create_dir(dir.path().join("partials")).unwrap();
+        let new_path = dir.path().join("partials/header.tera");
+        start_scanning(scan_config(dir.path()));
+
+        fs::rename(&old_path, &new_path).unwrap();
+        assert_eq!(VigilSpark::check_template_changes().renamed, vec![(old_path, new_path)]);
+    }
+
+    // A reload for `paths` as protocol 1 clients receive it, parsed back
+    fn reload_json(paths: Vec<PathBuf>) -> serde_json::Value {
+        let changes = DetectedChanges { modified: paths, ..DetectedChanges::default() };
+        let message = VigilMessage::reload("reload", changes.events(0));
+        serde_json::from_str(&message.to_text(PROTOCOL_VERSION)).unwrap()
+    }
+
+    #[test]
+    fn paths_with_spaces_colons_and_newlines_are_sent_intact() {
+        let _guard = lock();
+        VigilSpark::reset();
+        VigilSpark::with_config(VigilConfig::default(), "dev");
+
+        let message = reload_json(vec![PathBuf::from("templates/my page.html"), PathBuf::from("templates/a:b.html"), PathBuf::from("templates/line\nbreak.html")]);
+        let sent: Vec<&str> = message["events"].as_array().unwrap().iter().map(|event| event["path"].as_str().unwrap()).collect();
+        assert_eq!(sent, ["templates/my page.html", "templates/a:b.html", "templates/line\nbreak.html"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn invalid_utf8_paths_are_sent_lossily() {
+        use std::os::unix::ffi::OsStrExt;
+
+        let _guard = lock();
+        VigilSpark::reset();
+        VigilSpark::with_config(VigilConfig::default(), "dev");
+
+        let message = reload_json(vec![PathBuf::from(std::ffi::OsStr::from_bytes(b"templates/caf\xe9.html"))]);
+        assert_eq!(message["events"][0]["path"], "templates/caf\u{fffd}.html");
+        assert_eq!(message["count"], 1);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn files_with_spaces_and_colons_are_detected() {
+        let _guard = lock();
+        let dir = TempDir::new("unusual-names");
+        start_scanning(scan_config(dir.path()));
+
+        let spaced = dir.write("my page.html", "<p>spaces</p>");
+        let coloned = dir.write("a:b.html", "<p>colon</p>");
+        let mut created = VigilSpark::check_template_changes().created;
+        created.sort();
+        assert_eq!(created, vec![coloned, spaced]);
+    }
+
+    // Kinds of the events the next scan reports
+    fn scanned_kinds() -> Vec<ChangeKind> {
+        VigilSpark::check_template_changes().events(0).iter().map(|event| event.kind).collect()
+    }
+
+    #[test]
+    fn each_change_kind_comes_from_its_filesystem_operation() {
+        let _guard = lock();
+        let dir = TempDir::new("kinds");
+        let page = dir.write("page.html", "<p>old</p>");
+        start_scanning(scan_config(dir.path()));
+
+        let other = dir.write("other.html", "<p>new</p>");
+        assert_eq!(scanned_kinds(), [ChangeKind::Created]);
+
+        fs::write(&page, "<p>changed</p>").unwrap();
+        assert_eq!(scanned_kinds(), [ChangeKind::Modified]);
+
+        fs::rename(&other, dir.path().join("renamed.html")).unwrap();
+        assert_eq!(scanned_kinds(), [ChangeKind::Renamed]);
+
+        fs::remove_file(&page).unwrap();
+        assert_eq!(scanned_kinds(), [ChangeKind::Deleted]);
+    }
+
+    #[test]
+    fn change_kind_is_sent_with_each_event() {
+        let _guard = lock();
+        VigilSpark::reset();
+        VigilSpark::with_config(VigilConfig::default(), "dev");
+
+        let changes = DetectedChanges { created: vec![PathBuf::from("templates/new.html")], deleted: vec![PathBuf::from("templates/old.html")], ..DetectedChanges::default() };
+        let message: serde_json::Value = serde_json::from_str(&VigilMessage::reload("reload", changes.events(0)).to_text(PROTOCOL_VERSION)).unwrap();
+        assert_eq!(message["events"][0]["kind"], "deleted");
+        assert_eq!(message["events"][1]["kind"], "created");
+        assert_eq!(message["events"][1]["category"], "Template");
+    }
+
+    #[test]
+    fn two_saves_within_200ms_are_two_changes() {
+        let _guard = lock();
+        let dir = TempDir::new("subsecond");
+        let page = dir.write("page.html", "<p>0</p>");
+        let saved_at = mod_time(&page);
+        start_scanning(scan_config(dir.path()));
+
+        // Same size each time, so only the modification time tells the saves apart
+        fs::write(&page, "<p>1</p>").unwrap();
+        set_mod_time(&page, saved_at + Duration::from_millis(100));
+        assert_eq!(VigilSpark::check_template_changes().modified, vec![page.clone()]);
+
+        fs::write(&page, "<p>2</p>").unwrap();
+        set_mod_time(&page, saved_at + Duration::from_millis(200));
+        assert_eq!(VigilSpark::check_template_changes().modified, vec![page]);
+    }
+
+    #[test]
+    fn changes_during_the_cooldown_are_sent_once_it_ends() {
+        let _guard = lock();
+        let dir = TempDir::new("cooldown");
+        let first = dir.write("first.html", "<p>old</p>");
+        let second = dir.write("second.html", "<p>old</p>");
+        let config = VigilConfig { refresh_interval: 20, debounce_ms: 20, cooldown_period: 500, hash_check: false, ..scan_config(dir.path()) };
+        start_scanning(config.clone());
+        LAST_RELOAD_TIME.store(0, Ordering::SeqCst);
+
+        // Polling only, so the test doesn't depend on native events arriving in time
+        let mut detector = ChangeDetector { config, file_watcher: None, catalyst_mod_time: None, missing_dirs: Vec::new() };
+        let runtime = rocket::tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+        runtime.block_on(async {
+            fs::write(&first, "<p>first save</p>").unwrap();
+            let changes = detector.next_changes().await.expect("first save detected");
+            assert_eq!(changes.modified, vec![first.clone()]);
+            let reloaded_at = std::time::Instant::now();
+
+            fs::write(&second, "<p>second save</p>").unwrap();
+            let changes = detector.next_changes().await.expect("second save queued instead of dropped");
+            assert_eq!(changes.modified, vec![second.clone()]);
+            assert!(reloaded_at.elapsed() >= Duration::from_millis(400));
+        });
+    }
+
+    // Resolve the watch directories from a Catalyst.toml snippet and VIGIL_WATCH_DIRS / VIGIL_WATCH_MODE
+    fn resolved_watch_dirs(toml: Option<&str>, dirs: Option<&str>, mode: Option<&str>) -> Vec<PathBuf> {
+        let toml_config = toml.map(|text| toml::from_str::<toml::Value>(text).unwrap());
+        for (key, value) in [("VIGIL_WATCH_DIRS", dirs), ("VIGIL_WATCH_MODE", mode)] {
+            match value {
+                Some(value) => std::env::set_var(key, value),
+                None => std::env::remove_var(key),
+            }
+        }
+        let resolved = VigilSpark::get_config_watch_dirs(&toml_config, vec![WatchDirConfig::new("templates")]);
+        std::env::remove_var("VIGIL_WATCH_DIRS");
+        std::env::remove_var("VIGIL_WATCH_MODE");
+        resolved.into_iter().map(|dir| dir.path).collect()
+    }
+
+    #[test]
+    fn watch_dirs_from_toml_replace_the_defaults() {
+        let _guard = lock();
+        let toml = "[spark.vigil]\nwatch_dirs = [\"views\", \"emails\"]";
+        assert_eq!(resolved_watch_dirs(Some(toml), None, None), vec![PathBuf::from("views"), PathBuf::from("emails")]);
+        assert_eq!(resolved_watch_dirs(None, None, None), vec![PathBuf::from("templates")]);
+    }
+
+    #[test]
+    fn env_watch_dirs_are_appended_by_default() {
+        let _guard = lock();
+        let dir = TempDir::new("env-append");
+        let env_dirs = dir.path().display().to_string();
+        let toml = "[spark.vigil]\nwatch_dirs = [\"views\"]";
+        assert_eq!(resolved_watch_dirs(Some(toml), Some(&env_dirs), None), vec![PathBuf::from("views"), dir.path().to_path_buf()]);
+        assert_eq!(resolved_watch_dirs(None, Some(&env_dirs), Some("append")), vec![PathBuf::from("templates"), dir.path().to_path_buf()]);
+        // The leading `+` from older setups means the same as append
+        assert_eq!(resolved_watch_dirs(Some(toml), Some(&format!("+{}", env_dirs)), None), vec![PathBuf::from("views"), dir.path().to_path_buf()]);
+    }
+
+    #[test]
+    fn env_watch_dirs_replace_the_list_in_replace_mode() {
+        let _guard = lock();
+        let first = TempDir::new("env-replace-first");
+        let second = TempDir::new("env-replace-second");
+        let env_dirs = format!("{}, {}", first.path().display(), second.path().display());
+        let toml = "[spark.vigil]\nwatch_dirs = [\"views\"]";
+        assert_eq!(resolved_watch_dirs(Some(toml), Some(&env_dirs), Some("Replace")), vec![first.path().to_path_buf(), second.path().to_path_buf()]);
+    }
+
+    #[test]
+    fn env_watch_dirs_skip_missing_and_duplicate_entries() {
+        let _guard = lock();
+        let dir = TempDir::new("env-skip");
+        let missing = dir.path().join("missing");
+        let existing = dir.path().display().to_string();
+        let toml = format!("[spark.vigil]\nwatch_dirs = [{:?}]", existing);
+        let env_dirs = format!("{},{}", missing.display(), existing);
+        assert_eq!(resolved_watch_dirs(Some(&toml), Some(&env_dirs), None), vec![dir.path().to_path_buf()]);
+        assert!(resolved_watch_dirs(Some(&toml), Some(&missing.display().to_string()), Some("replace")).is_empty());
+    }
+
+    #[test]
+    fn dry_run_changes_leave_the_heartbeat_timestamp_alone() {
+        let _guard = lock();
+        let dir = TempDir::new("dry-run");
+        let page = dir.write("index.html", "<p>1</p>");
+        start_scanning(VigilConfig { dry_run: true, ..scan_config(dir.path()) });
+        let heartbeat_timestamp = LAST_MOD_TIME.load(Ordering::SeqCst);
+
+        fs::write(&page, "<p>2</p>").unwrap();
+        set_mod_time(&page, mod_time(&page) + Duration::from_secs(10));
+        assert_eq!(VigilSpark::check_template_changes().modified, vec![page]);
+        assert_eq!(LAST_MOD_TIME.load(Ordering::SeqCst), heartbeat_timestamp);
+    }
+
+    #[test]
+    fn rewrites_that_keep_the_mod_time_are_detected_by_size() {
+        let _guard = lock();
+        let dir = TempDir::new("pinned-mtime");
+        let page = dir.write("page.html", "<p>old</p>");
+        let original_time = mod_time(&page);
+        start_scanning(VigilConfig { change_detection: ChangeDetection::Mtime, ..scan_config(dir.path()) });
+
+        fs::write(&page, "<p>a longer rewrite</p>").unwrap();
+        set_mod_time(&page, original_time);
+        assert_eq!(VigilSpark::check_template_changes().modified, vec![page]);
+    }
+
+    fn reload_seq(message: &VigilMessage) -> u64 {
+        match message {
+            VigilMessage::Reload { seq, .. } => *seq,
+            other => panic!("expected a reload, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn reconnecting_clients_are_sent_the_reloads_they_missed() {
+        let _guard = lock();
+        let dir = TempDir::new("replay");
+        start_scanning(scan_config(dir.path()));
+
+        // The client disconnects after the latest reload, files are touched, then it reconnects
+        let since = VigilMessage::last_sequence();
+        let first = VigilMessage::reload("reload", Vec::new());
+        first.remember();
+        let second = VigilMessage::reload("css", Vec::new());
+        second.remember();
+
+        let replayed: Vec<u64> = VigilMessage::replay_since(since).iter().map(reload_seq).collect();
+        assert_eq!(replayed, vec![reload_seq(&first), reload_seq(&second)]);
+        let replayed: Vec<u64> = VigilMessage::replay_since(reload_seq(&first)).iter().map(reload_seq).collect();
+        assert_eq!(replayed, vec![reload_seq(&second)]);
+        assert!(VigilMessage::replay_since(reload_seq(&second)).is_empty());
+    }
+
+    #[test]
+    fn reconnecting_after_the_missed_reloads_were_evicted_gets_a_resync() {
+        let _guard = lock();
+        let dir = TempDir::new("replay-evicted");
+        start_scanning(scan_config(dir.path()));
+
+        let since = VigilMessage::last_sequence();
+        for _ in 0..=REPLAY_CAPACITY {
+            VigilMessage::reload("reload", Vec::new()).remember();
+        }
+        assert!(matches!(VigilMessage::replay_since(since).as_slice(), [VigilMessage::Resync]));
+        // A sequence number from before this server started
+        assert!(matches!(VigilMessage::replay_since(VigilMessage::sequence_start() - 1).as_slice(), [VigilMessage::Resync]));
+    }
+
+    #[get("/origin-check")]
+    fn origin_check(_origin: AllowedOrigin) -> &'static str {
+        "ok"
+    }
+
+    // Status of a request with the given Origin and Host to a route behind the reload socket's guard
+    fn origin_check_status(config: VigilConfig, origin: Option<&str>, host: &str) -> Status {
+        VigilSpark::reset();
+        VigilSpark::with_config(config, "dev");
+        let client = rocket::local::blocking::Client::untracked(rocket::build().mount("/", routes![origin_check])).unwrap();
+        let mut request = client.get("/origin-check").header(Header::new("Host", host.to_string()));
+        if let Some(origin) = origin {
+            request = request.header(Header::new("Origin", origin.to_string()));
+        }
+        request.dispatch().status()
+    }
+
+    #[test]
+    fn reload_socket_rejects_foreign_origins() {
+        let _guard = lock();
+        let dir = TempDir::new("origin-foreign");
+        assert_eq!(origin_check_status(scan_config(dir.path()), Some("https://evil.example"), "127.0.0.1:8000"), Status::Forbidden);
+
+        // cors_origins opens the /vigil routes to other sites, not the socket
+        let config = VigilConfig { cors_origins: vec![String::from("*")], ..scan_config(dir.path()) };
+        assert_eq!(origin_check_status(config, Some("https://evil.example"), "127.0.0.1:8000"), Status::Forbidden);
+    }
+
+    #[test]
+    fn reload_socket_accepts_localhost_its_own_host_and_missing_origins() {
+        let _guard = lock();
+        let dir = TempDir::new("origin-allowed");
+        for origin in [Some("http://localhost:5173"), Some("http://127.0.0.1:3000"), Some("https://dev.example.test"), None] {
+            assert_eq!(origin_check_status(scan_config(dir.path()), origin, "dev.example.test"), Status::Ok, "origin {:?}", origin);
+        }
+
+        let config = VigilConfig { allowed_origins: vec![String::from("https://tools.example.test")], ..scan_config(dir.path()) };
+        assert_eq!(origin_check_status(config, Some("https://tools.example.test"), "dev.example.test"), Status::Ok);
+    }
+
+    // Directories modified a minute ago, so the scanner reuses their cached listings
+    #[cfg(unix)]
+    fn settle_dir(path: &Path) {
+        fs::File::open(path).and_then(|dir| dir.set_modified(SystemTime::now() - Duration::from_secs(60))).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn bulk_rewrites_across_cached_directories_are_all_reported() {
+        let _guard = lock();
+        let dir = TempDir::new("bulk");
+        let mut pages: Vec<PathBuf> = (0..500).map(|i| dir.write(&format!("section{}/page{}.html", i % 5, i), "<p>old</p>")).collect();
+        for i in 0..10 {
+            dir.write(&format!("removed/page{}.html", i), "<p>old</p>");
+        }
+        for subdir in ["section0", "section1", "section2", "section3", "section4", "removed", ""] {
+            settle_dir(&dir.path().join(subdir));
+        }
+        start_scanning(scan_config(dir.path()));
+        assert!(VigilSpark::check_template_changes().is_empty());
+
+        for page in &pages {
+            fs::write(page, "<p>rewritten</p>").unwrap();
+        }
+        fs::remove_dir_all(dir.path().join("removed")).unwrap();
+
+        let changes = VigilSpark::check_template_changes();
+        pages.sort();
+        assert_eq!(changes.modified, pages);
+        assert_eq!(changes.deleted, vec![dir.path().join("removed")]);
+    }
+
+    #[test]
+    fn excluded_directories_are_never_entered() {
+        let _guard = lock();
+        let dir = TempDir::new("excluded");
+        let vendored = dir.write("node_modules/pkg/index.html", "<p>old</p>");
+        let generated = dir.write("generated/page.html", "<p>old</p>");
+        dir.write("page.html", "<p>old</p>");
+        let mut config = scan_config(dir.path());
+        config.exclude_dirs.push(String::from("generated"));
+        start_scanning(config);
+
+        fs::write(&vendored, "<p>rewritten</p>").unwrap();
+        fs::write(&generated, "<p>rewritten</p>").unwrap();
+        assert!(VigilSpark::check_template_changes().is_empty());
+
+        let listings = DIRECTORY_LISTINGS.lock().unwrap();
+        let listed: Vec<&PathBuf> = listings.as_ref().map(|listings| listings.keys().collect()).unwrap_or_default();
+        assert!(!listed.is_empty());
+        assert!(listed.iter().all(|path| !path.ends_with("node_modules") && !path.ends_with("pkg") && !path.ends_with("generated")), "listed {:?}", listed);
+    }
+
+    #[test]
+    fn editor_swap_backup_and_lock_files_are_ignored() {
+        let _guard = lock();
+        let dir = TempDir::new("editor-temp");
+        let page = dir.write("index.html", "<p>old</p>");
+        // Watch every file, hidden ones included, so only the editor artifact check can skip them
+        let config = VigilConfig { watch_hidden: true, watch_include_patterns: vec![glob::Pattern::new("*").unwrap()], ..scan_config(dir.path()) };
+        start_scanning(config);
+
+        for name in [".file.tera.swp", ".index.html.swx", "index.html~", ".#index.html", "4913"] {
+            dir.write(name, "editor state");
+        }
+        assert!(VigilSpark::check_template_changes().is_empty());
+
+        fs::write(&page, "<p>saved</p>").unwrap();
+        assert_eq!(VigilSpark::check_template_changes().modified, vec![page]);
+    }
+
+    #[test]
+    fn checked_out_files_with_old_mod_times_are_created() {
+        let _guard = lock();
+        let dir = TempDir::new("checkout");
+        dir.write("index.html", "<p>home</p>");
+        start_scanning(scan_config(dir.path()));
+
+        // git checkout and `cp -p` keep the mod time from long before the scanner started
+        let page = dir.write("about.html", "<p>about</p>");
+        set_mod_time(&page, SystemTime::now() - Duration::from_secs(30 * 24 * 60 * 60));
+        assert_eq!(VigilSpark::check_template_changes().created, vec![page]);
+    }
+
+    #[test]
+    fn configured_extensions_replace_the_defaults() {
+        let _guard = lock();
+        let dir = TempDir::new("custom-extensions");
+        let partial = dir.write("partials/header.hbs", "<h1>old</h1>");
+        let stylesheet = dir.write("site.css", "body {}");
+        let extensions = ["tera", "html", "hbs"].iter().map(|ext| ext.to_string()).collect();
+        start_scanning(VigilConfig { extensions, ..scan_config(dir.path()) });
+
+        fs::write(&partial, "<h1>new title</h1>").unwrap();
+        fs::write(&stylesheet, "body { color: red; }").unwrap();
+        assert_eq!(VigilSpark::check_template_changes().modified, vec![partial]);
+    }
+}